@@ -0,0 +1,201 @@
+use std::{collections::HashMap, fs::File, io::{ErrorKind, Read, Write}, os::fd::OwnedFd, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use smithay::{input::Seat, reexports::calloop::{Interest, Mode, PostAction, RegistrationToken, generic::Generic}, wayland::selection::{SelectionSource, SelectionTarget, data_device::{request_data_device_client_selection, set_data_device_selection}, primary_selection::{request_primary_client_selection, set_primary_selection}}};
+use wayland_server::Client;
+
+use crate::compositor::{Waycrust, client_disconnected};
+
+// larger selections aren't worth keeping in memory
+const MAX_SELECTION_SIZE: usize = 16 * 1024 * 1024;
+// owners that don't finish sending a mime type by then have it left out
+const SELECTION_READ_TIMEOUT: Duration = Duration::from_secs(5);
+// writable pipes always take this much without blocking
+const PIPE_BUF: usize = 4096;
+// text in the first of these the owner offers and its first image type are copied, other types are
+// mostly the same contents in another format
+const TEXT_MIME_TYPES: [&str; 4] = ["text/plain;charset=utf-8", "UTF8_STRING", "text/plain", "STRING"];
+
+/// Contents of a selection by mime type, filled on the event loop while the owner sends them.
+pub type SelectionData = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Copy of a client selection, taken over by the compositor once the client goes away.
+pub struct PersistedSelection {
+    owner: Client,
+    mime_types: Vec<String>,
+    data: SelectionData,
+    reads: Vec<(String, RegistrationToken)>,    // mime types still being copied
+    deadline: Instant
+}
+
+#[derive(Default)]
+pub struct PersistedSelections {
+    clipboard: Option<PersistedSelection>,
+    primary: Option<PersistedSelection>
+}
+
+impl PersistedSelections {
+    fn get_mut(&mut self, target: SelectionTarget) -> &mut Option<PersistedSelection> {
+        match target {
+            SelectionTarget::Clipboard => &mut self.clipboard,
+            SelectionTarget::Primary => &mut self.primary
+        }
+    }
+}
+
+impl Waycrust {
+    fn persist_enabled(&self, target: SelectionTarget) -> bool {
+        match target {
+            SelectionTarget::Clipboard => self.config.clipboard.persist,
+            SelectionTarget::Primary => self.config.clipboard.persist_primary
+        }
+    }
+
+    /// Starts copying a new client selection, the copy of the previous one is dropped.
+    pub fn selection_changed(&mut self, target: SelectionTarget, source: Option<SelectionSource>, seat: &Seat<Self>) {
+        self.drop_persisted_selection(target);
+        let Some(source) = source else {
+            return
+        };
+        if !self.persist_enabled(target) {
+            return
+        }
+        // data control clients set selections without having focus, so the owner comes from the source
+        let Some(owner) = source.client() else {
+            return
+        };
+
+        let data = SelectionData::default();
+        let mime_types = preferred_mime_types(&source.mime_types());
+        let mut reads = vec![];
+        for mime_type in mime_types.iter() {
+            let (reader, writer) = match std::io::pipe() {
+                Ok(pipe) => pipe,
+                Err(err) => {
+                    println!("cannot copy selection: {}", err);
+                    break
+                }
+            };
+            let request = match target {
+                SelectionTarget::Clipboard => request_data_device_client_selection(seat, mime_type.clone(), writer.into()).is_ok(),
+                SelectionTarget::Primary => request_primary_client_selection(seat, mime_type.clone(), writer.into()).is_ok()
+            };
+            if !request {
+                continue
+            }
+
+            let data = data.clone();
+            let read_mime_type = mime_type.clone();
+            let mut contents = vec![];
+            let source = Generic::new(reader, Interest::READ, Mode::Level);
+            let token = self.loop_handle.insert_source(source, move |_, reader, state| {
+                let mut chunk = [0; PIPE_BUF];
+                match reader.read(&mut chunk) {
+                    Ok(0) => {
+                        data.lock().unwrap().insert(read_mime_type.clone(), std::mem::take(&mut contents));
+                    }
+                    Ok(len) if contents.len() + len <= MAX_SELECTION_SIZE => {
+                        contents.extend_from_slice(&chunk[..len]);
+                        return Ok(PostAction::Continue)
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => return Ok(PostAction::Continue),
+                    // too large or broken, the mime type isn't offered
+                    _ => {}
+                }
+                state.selection_read_finished(target, &data, &read_mime_type);
+                Ok(PostAction::Remove)
+            });
+            match token {
+                Ok(token) => reads.push((mime_type.clone(), token)),
+                Err(err) => println!("cannot copy selection: {}", err.error)
+            }
+        }
+        *self.persisted_selections.get_mut(target) = Some(PersistedSelection {
+            owner,
+            mime_types,
+            data,
+            reads,
+            deadline: Instant::now() + SELECTION_READ_TIMEOUT
+        });
+    }
+
+    fn selection_read_finished(&mut self, target: SelectionTarget, data: &SelectionData, mime_type: &str) {
+        // the selection may have been replaced in the meantime
+        if let Some(selection) = self.persisted_selections.get_mut(target).as_mut().filter(|s| Arc::ptr_eq(&s.data, data)) {
+            selection.reads.retain(|(m, _)| m != mime_type);
+        }
+    }
+
+    fn drop_persisted_selection(&mut self, target: SelectionTarget) {
+        if let Some(selection) = self.persisted_selections.get_mut(target).take() {
+            for (_, token) in selection.reads {
+                self.loop_handle.remove(token);
+            }
+        }
+    }
+
+    /// Takes over selections whose owner disconnected, called once per loop iteration. Copies still
+    /// in progress are waited for until the deadline.
+    pub fn persist_selections(&mut self) {
+        let seat = self.seat.clone();
+        for target in [SelectionTarget::Clipboard, SelectionTarget::Primary] {
+            let Some(selection) = self.persisted_selections.get_mut(target).as_mut() else {
+                continue
+            };
+            if selection.deadline <= Instant::now() {
+                for (_, token) in selection.reads.drain(..) {
+                    self.loop_handle.remove(token);
+                }
+            }
+
+            let slot = self.persisted_selections.get_mut(target);
+            if !slot.as_ref().is_some_and(|s| client_disconnected(&s.owner) && s.reads.is_empty()) {
+                continue
+            }
+            let Some(selection) = slot.take() else {
+                continue
+            };
+            // mime types that couldn't be copied aren't offered
+            let copied = selection.data.lock().unwrap().keys().cloned().collect::<Vec<_>>();
+            let mime_types: Vec<String> = selection.mime_types.into_iter().filter(|m| copied.contains(m)).collect();
+            if mime_types.is_empty() {
+                continue
+            }
+            match target {
+                SelectionTarget::Clipboard => set_data_device_selection(&self.display_handle, &seat, mime_types, selection.data),
+                SelectionTarget::Primary => set_primary_selection(&self.display_handle, &seat, mime_types, selection.data)
+            }
+        }
+    }
+
+    /// Sends a persisted selection to the client pasting it. It's written as the client reads it, so
+    /// a client that doesn't read can't block the loop.
+    pub fn send_persisted_selection(&mut self, mime_type: String, fd: OwnedFd, data: &SelectionData) {
+        let Some(contents) = data.lock().unwrap().get(&mime_type).cloned() else {
+            return
+        };
+        let mut written = 0;
+        let source = Generic::new(File::from(fd), Interest::WRITE, Mode::Level);
+        let inserted = self.loop_handle.insert_source(source, move |_, file, _| {
+            let end = contents.len().min(written + PIPE_BUF);
+            match file.write(&contents[written..end]) {
+                Ok(len) => written += len,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                // the client closed the pipe
+                Err(_) => return Ok(PostAction::Remove)
+            }
+            if written < contents.len() {
+                return Ok(PostAction::Continue)
+            }
+            Ok(PostAction::Remove)
+        });
+        if let Err(err) = inserted {
+            println!("cannot send selection: {}", err.error);
+        }
+    }
+}
+
+fn preferred_mime_types(offered: &[String]) -> Vec<String> {
+    let text = TEXT_MIME_TYPES.iter().find_map(|text| offered.iter().find(|m| m == text));
+    let image = offered.iter().find(|m| m.starts_with("image/"));
+    text.into_iter().chain(image).cloned().collect()
+}
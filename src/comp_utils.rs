@@ -1,10 +1,15 @@
-use std::{collections::VecDeque, fs};
+use std::{collections::{HashMap, VecDeque}, fs};
 
-use smithay::{input::SeatState, utils::SERIAL_COUNTER, wayland::{compositor::CompositorState, selection::data_device::DataDeviceState, shell::xdg::{ToplevelSurface, XdgShellState}, shm::ShmState}};
+use smithay::{backend::input::KeyState, desktop::{WindowSurfaceType, utils::under_from_surface_tree}, input::{SeatState, keyboard::{FilterResult, Layout}, pointer::CursorImageStatus}, reexports::calloop::LoopHandle, utils::{Logical, Point, SERIAL_COUNTER}, wayland::{compositor::CompositorState, cursor_shape::CursorShapeManagerState, keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState, pointer_constraints::PointerConstraintsState, pointer_gestures::PointerGesturesState, relative_pointer::RelativePointerManagerState, selection::{data_device::DataDeviceState, primary_selection::PrimarySelectionState, wlr_data_control::DataControlState as WlrDataControlState, ext_data_control::DataControlState as ExtDataControlState}, shell::xdg::{ToplevelSurface, XdgShellState}, shm::ShmState, tablet_manager::TabletManagerState, virtual_keyboard::VirtualKeyboardManagerState, input_method::InputMethodManagerState, text_input::TextInputManagerState}};
 use wayland_protocols::xdg::shell::server::xdg_toplevel;
-use wayland_server::Display;
+use wayland_server::{Client, Display, protocol::wl_surface::WlSurface};
+use xkbcommon::xkb::Keycode;
 
-use crate::{compositor::{TopLevelWindows, Waycrust}, config::Config};
+use crate::{compositor::{TopLevelWindows, Waycrust, client_privileged}, config::Config, cursor::Cursor, handlers::input::{keybinds::KeybindState, modifiers::KeybindXkb}, virtual_input::VirtualPointerManagerState};
+
+// xkb keycodes of the lock keys
+const KEY_NUMLOCK: u32 = 69 + 8;
+const KEY_CAPSLOCK: u32 = 58 + 8;
 
 
 
@@ -18,16 +23,70 @@ impl Waycrust {
         if let Some(ref s) = surface {
             kbd.set_focus(self, Some(s.wl_surface().clone()), SERIAL_COUNTER.next_serial());
 
-            s.with_pending_state(|state| {
-                state.states.set(xdg_toplevel::State::Fullscreen);
-                state.size = self.size
-            });
-            s.send_configure();
+            if self.config.focus.raise_on_focus {
+                if let Some(idx) = self.toplevels.floating.iter().position(|f| f == s) {
+                    let raised = self.toplevels.floating.remove(idx);
+                    self.toplevels.floating.push(raised);
+                }
+            }
+
+            self.configure_toplevel(s);
         } else {
             kbd.set_focus(self, None, SERIAL_COUNTER.next_serial());
         }
 
         self.toplevels.focused = surface;
+        self.restore_window_layout();
+    }
+
+    /// Finds the toplevel and its (sub)surface under `point`, the returned location is the surface origin.
+    pub fn surface_under(&self, point: Point<f64, Logical>) -> Option<(ToplevelSurface, WlSurface, Point<f64, Logical>)> {
+        let floating = self.toplevels.floating.iter().rev().map(|top| {
+            let location = self.toplevels.properties.get(top.wl_surface()).map(|p| p.location).unwrap_or_default();
+            (top, location)
+        });
+        let tiled = self.toplevels.toplevels.front().map(|top| (top, Point::from((0, 0))));
+
+        floating.chain(tiled).find_map(|(top, location)| {
+            under_from_surface_tree(top.wl_surface(), point, location, WindowSurfaceType::ALL)
+                .map(|(surface, loc)| (top.clone(), surface, loc.to_f64()))
+        })
+    }
+
+    pub fn configure_toplevel(&self, surface: &ToplevelSurface) {
+        let floating = self.toplevels.floating.contains(surface);
+        let size = self.toplevels.properties.get(surface.wl_surface()).and_then(|p| p.size);
+
+        surface.with_pending_state(|state| {
+            if floating {
+                state.states.unset(xdg_toplevel::State::Fullscreen);
+                state.size = size;
+            } else {
+                state.states.set(xdg_toplevel::State::Fullscreen);
+                state.size = self.size;
+            }
+        });
+        surface.send_configure();
+    }
+
+    pub fn set_floating(&mut self, surface: &ToplevelSurface, floating: bool) {
+        self.toplevels.toplevels.retain(|s| s != surface);
+        self.toplevels.floating.retain(|s| s != surface);
+
+        if floating {
+            let output = self.size.unwrap_or_default();
+            let props = self.toplevels.properties.entry(surface.wl_surface().clone()).or_default();
+            let size = *props.size.get_or_insert_with(|| (output.w / 2, output.h / 2).into());
+            props.location = ((output.w - size.w) / 2, (output.h - size.h) / 2).into();
+
+            self.toplevels.floating.push(surface.clone());
+        } else if self.toplevels.focused.as_ref() == Some(surface) {
+            self.toplevels.toplevels.push_front(surface.clone());
+        } else {
+            self.toplevels.toplevels.push_back(surface.clone());
+        }
+
+        self.configure_toplevel(surface);
     }
 
     pub fn next_toplevel(&mut self) {
@@ -56,7 +115,7 @@ impl Waycrust {
         self.focus_toplevel(next);
     }
 
-    pub fn init() -> Result<(Self, Display<Self>), Box<dyn std::error::Error>> {
+    pub fn init(loop_handle: LoopHandle<'static, Self>) -> Result<(Self, Display<Self>), Box<dyn std::error::Error>> {
 
         let display: Display<Waycrust> = Display::new()?;
         let dh = display.handle();
@@ -65,6 +124,11 @@ impl Waycrust {
         let shm_state = ShmState::new::<Waycrust>(&dh, vec![]);
         let mut seat_state = SeatState::new();
         let seat = seat_state.new_wl_seat(&dh, "winit");
+        let config = load_config();
+        let primary_selection_state = PrimarySelectionState::new::<Waycrust>(&dh);
+        // clipboard managers read every selection, so they have to be privileged
+        let wlr_data_control_state = WlrDataControlState::new::<Waycrust, _>(&dh, Some(&primary_selection_state), client_privileged);
+        let ext_data_control_state = ExtDataControlState::new::<Waycrust, _>(&dh, Some(&primary_selection_state), client_privileged);
 
         Ok((Waycrust {
             compositor_state,
@@ -72,16 +136,193 @@ impl Waycrust {
             shm_state,
             seat_state,
             data_device_state: DataDeviceState::new::<Waycrust>(&dh),
-            toplevels: TopLevelWindows { toplevels: VecDeque::new(), focused: None },
+            primary_selection_state,
+            wlr_data_control_state,
+            ext_data_control_state,
+            cursor_shape_manager_state: CursorShapeManagerState::new::<Waycrust>(&dh),
+            pointer_gestures_state: PointerGesturesState::new::<Waycrust>(&dh),
+            tablet_manager_state: TabletManagerState::new::<Waycrust>(&dh),
+            pointer_constraints_state: PointerConstraintsState::new::<Waycrust>(&dh),
+            relative_pointer_manager_state: RelativePointerManagerState::new::<Waycrust>(&dh),
+            keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState::new::<Waycrust>(&dh),
+            virtual_keyboard_manager_state: VirtualKeyboardManagerState::new::<Waycrust, _>(&dh, client_privileged),
+            virtual_pointer_manager_state: VirtualPointerManagerState::new(&dh),
+            input_method_manager_state: InputMethodManagerState::new::<Waycrust, _>(&dh, client_privileged),
+            text_input_manager_state: TextInputManagerState::new::<Waycrust>(&dh),
+            toplevels: TopLevelWindows {
+                pending: vec![],
+                toplevels: VecDeque::new(),
+                floating: vec![],
+                focused: None,
+                properties: HashMap::new(),
+                scratchpad: vec![],
+                named_scratchpads: HashMap::new(),
+                pending_scratchpads: vec![]
+            },
             seat,
+            display_handle: dh.clone(),
+            loop_handle,
             size: None,
-            config: load_config(),
-            flipped: false
+            cursor: Cursor::load(&config.cursor),
+            cursor_status: CursorImageStatus::default_named(),
+            tool_location: None,
+            tool_cursor_status: CursorImageStatus::default_named(),
+            keybinds: KeybindState::new(&config.keyboard),
+            layout: 0,
+            touch_slots: HashMap::new(),
+            gesture: None,
+            broken_constraint: None,
+            libinput_devices: vec![],
+            keyboard_device: None,
+            keyboard_device_config: None,
+            virtual_input: false,
+            input_method_popups: vec![],
+            persisted_selections: Default::default(),
+            drag: None,
+            config,
+            flipped: false,
+            ipc_events: vec![]
         }, display))
     }
+
+    /// Whether the executable of the process behind the client is listed as privileged in the config.
+    /// Process names can be changed by the process itself, the executable can't.
+    pub fn is_privileged_process(&self, client: &Client) -> bool {
+        let Ok(credentials) = client.get_credentials(&self.display_handle) else {
+            return false
+        };
+        let Ok(exe) = fs::read_link(format!("/proc/{}/exe", credentials.pid)) else {
+            return false
+        };
+        // listed paths may be symlinks, the link in /proc always points at the real file
+        self.config.privileged_clients.iter()
+            .any(|path| fs::canonicalize(path).is_ok_and(|path| path == exe))
+    }
+
+    /// Reads the config again and applies it, the old one is kept if the new one doesn't parse.
+    pub fn reload_config(&mut self) {
+        let config = match read_config() {
+            Ok(config) => config,
+            Err(err) => {
+                println!("invalid config: {}", err);
+                return
+            }
+        };
+        self.config = config;
+
+        self.apply_keyboard_config();
+        self.reconfigure_libinput_devices();
+        self.cursor = Cursor::load(&self.config.cursor);
+        if self.keybinds.mode.as_ref().is_some_and(|m| !self.config.modes.contains_key(m)) {
+            self.enter_mode(None);
+        }
+        // windows not mapped yet get their rules on the initial commit
+        let windows: Vec<ToplevelSurface> = self.xdg_shell_state.toplevel_surfaces().iter()
+            .filter(|w| !self.toplevels.pending.contains(w))
+            .cloned()
+            .collect();
+        for window in windows {
+            self.apply_window_rules(&window);
+        }
+    }
+
+    pub fn apply_keyboard_config(&mut self) {
+        // device entries may have moved, the next key picks its keymap again
+        self.keyboard_device = None;
+        self.keyboard_device_config = None;
+        self.apply_xkb_config();
+        self.set_repeat_info(self.config.keyboard.repeat_delay, self.config.keyboard.repeat_rate);
+    }
+
+    pub fn apply_xkb_config(&mut self) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return
+        };
+        let config = self.keyboard_config().clone();
+        let locks = keyboard.modifier_state();
+        if let Err(err) = keyboard.set_xkb_config(self, (&config).into()) {
+            println!("cannot apply keyboard config: {:?}", err);
+        }
+        self.keybinds.xkb = KeybindXkb::new(&config);
+        self.check_keybind_modifiers();
+        self.keybinds.chord = None;
+        self.keybinds.repeating = None;
+
+        // a new keymap starts at its first layout, keep the one in use if it still has it. It isn't a
+        // switch made in the focused window, so per window layouts are left alone.
+        let layout = if self.layout < self.layout_names().len() as u32 { self.layout } else { 0 };
+        keyboard.with_xkb_state(self, |mut context| context.set_layout(Layout(layout)));
+        self.layout = layout;
+        self.ipc_events.push(format!("LAYOUT {}", self.layout_name()));
+
+        // locks start off as well, they carry over across keyboards and reloads. The configured ones
+        // are only set on startup. Real modifiers, which the locks are, keep their indices in every keymap.
+        let mut mods = keyboard.modifier_state();
+        mods.caps_lock = locks.caps_lock;
+        mods.num_lock = locks.num_lock;
+        mods.serialized.locked = (mods.serialized.locked & !0xff) | (locks.serialized.locked & 0xff);
+        keyboard.set_modifier_state(mods);
+    }
+
+    pub fn set_repeat_info(&mut self, delay: i32, rate: i32) {
+        self.config.keyboard.repeat_delay = delay;
+        self.config.keyboard.repeat_rate = rate;
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            keyboard.change_repeat_info(rate, delay);
+        }
+    }
+
+    /// Turns Num Lock and Caps Lock on or off as configured, by pressing their keys.
+    pub fn apply_lock_keys(&mut self) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return
+        };
+        let mods = keyboard.modifier_state();
+        let keys = [
+            (KEY_NUMLOCK, mods.num_lock != self.config.keyboard.numlock),
+            (KEY_CAPSLOCK, mods.caps_lock != self.config.keyboard.capslock)
+        ];
+        for (key, toggle) in keys {
+            if !toggle {
+                continue
+            }
+            for key_state in [KeyState::Pressed, KeyState::Released] {
+                keyboard.input::<(), _>(
+                    self,
+                    Keycode::new(key),
+                    key_state,
+                    SERIAL_COUNTER.next_serial(),
+                    0,
+                    |_, _, _| FilterResult::Intercept(())
+                );
+            }
+        }
+    }
+}
+
+/// Returns whether the command started.
+pub fn spawn_command(command: &str) -> bool {
+    let (command, args) = {
+        let mut iter = shlex::Shlex::new(command);
+        let command = iter.next().unwrap_or_default().to_string();
+        let args: Vec<String> = iter.collect();
+        (command, args)
+    };
+
+    match std::process::Command::new(&command).args(args).spawn() {
+        Ok(_) => true,
+        Err(err) => {
+            println!("cannot spawn {}: {}", command, err);
+            false
+        }
+    }
 }
 
 fn load_config() -> Config {
+    read_config().unwrap()
+}
+
+fn read_config() -> Result<Config, ron::error::SpannedError> {
     let config_file = if fs::exists("./config.ron").is_ok_and(|b| b) {
         println!("config found next to bin");
         "./config.ron"
@@ -90,14 +331,14 @@ fn load_config() -> Config {
         "~/.config/waycrust/config.ron"
     } else {
         println!("config not found");
-        return Config::default()
+        return Ok(Config::default())
     };
 
     if let Ok(content) = fs::read_to_string(config_file) {
         println!("reading config");
-        ron::from_str(&content).unwrap()
+        ron::from_str(&content)
     } else {
         println!("cannot read config");
-        Config::default()
+        Ok(Config::default())
     }
 }
\ No newline at end of file
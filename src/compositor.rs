@@ -1,13 +1,13 @@
-use std::collections::VecDeque;
+use std::{collections::{HashMap, VecDeque}, os::fd::OwnedFd, sync::atomic::{AtomicBool, Ordering}, time::Instant};
 
 use smithay::{
-    backend::renderer::utils::on_commit_buffer_handler, input::{Seat, SeatHandler, SeatState}, utils::Size, wayland::{
-        buffer::BufferHandler, compositor::{CompositorClientState, CompositorHandler, CompositorState}, selection::{SelectionHandler, data_device::{DataDeviceHandler, DataDeviceState, WaylandDndGrabHandler}}, shell::xdg::{ToplevelSurface, XdgShellState}, shm::{ShmHandler, ShmState}
-    }
+    backend::{input::TouchSlot, renderer::utils::on_commit_buffer_handler}, input::{Seat, SeatHandler, SeatState, pointer::{CursorImageStatus, PointerHandle}}, utils::{Logical, Point, Size}, wayland::{
+        buffer::BufferHandler, compositor::{CompositorClientState, CompositorHandler, CompositorState}, cursor_shape::CursorShapeManagerState, keyboard_shortcuts_inhibit::{KeyboardShortcutsInhibitHandler, KeyboardShortcutsInhibitState, KeyboardShortcutsInhibitor}, pointer_constraints::{PointerConstraintsHandler, PointerConstraintsState, with_pointer_constraint}, pointer_gestures::PointerGesturesState, relative_pointer::RelativePointerManagerState, selection::{SelectionHandler, SelectionSource, SelectionTarget, data_device::{DataDeviceHandler, DataDeviceState, set_data_device_focus}, primary_selection::{PrimarySelectionHandler, PrimarySelectionState, set_primary_focus}, wlr_data_control::{self, DataControlState as WlrDataControlState}, ext_data_control::{self, DataControlState as ExtDataControlState}}, shell::xdg::{ToplevelSurface, XdgShellState}, shm::{ShmHandler, ShmState}, tablet_manager::{TabletManagerState, TabletSeatHandler, TabletToolDescriptor}, virtual_keyboard::VirtualKeyboardManagerState, input_method::{InputMethodManagerState, PopupSurface}, text_input::TextInputManagerState
+    }, reexports::{calloop::LoopHandle, input}
 };
-use wayland_server::{Client, backend::{ClientData, ClientId, DisconnectReason}, protocol::{wl_buffer, wl_surface::WlSurface}};
+use wayland_server::{Client, DisplayHandle, Resource, backend::{ClientData, ClientId, DisconnectReason}, protocol::{wl_buffer, wl_surface::WlSurface}};
 
-use crate::config::Config;
+use crate::{clipboard::{PersistedSelections, SelectionData}, config::Config, dnd::Drag, cursor::Cursor, handlers::input::{gestures::ActiveGesture, keybinds::KeybindState}, virtual_input::VirtualPointerManagerState};
 
 
 
@@ -17,17 +17,80 @@ pub struct Waycrust {
     pub shm_state: ShmState,
     pub seat_state: SeatState<Self>,
     pub data_device_state: DataDeviceState,
+    pub primary_selection_state: PrimarySelectionState,
+    pub wlr_data_control_state: WlrDataControlState,
+    pub ext_data_control_state: ExtDataControlState,
+    pub cursor_shape_manager_state: CursorShapeManagerState,
+    pub pointer_gestures_state: PointerGesturesState,
+    pub tablet_manager_state: TabletManagerState,
+    pub pointer_constraints_state: PointerConstraintsState,
+    pub relative_pointer_manager_state: RelativePointerManagerState,
+    pub keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
+    pub virtual_keyboard_manager_state: VirtualKeyboardManagerState,
+    pub virtual_pointer_manager_state: VirtualPointerManagerState,
+    pub input_method_manager_state: InputMethodManagerState,
+    pub text_input_manager_state: TextInputManagerState,
+    pub display_handle: DisplayHandle,
+    pub loop_handle: LoopHandle<'static, Self>,
     pub seat: Seat<Self>,
     pub toplevels: TopLevelWindows,
 
     pub size: Option<Size<i32, smithay::utils::Logical>>,
     pub config: Config,
-    pub flipped: bool
+    pub flipped: bool,
+    pub cursor_status: CursorImageStatus,
+    pub tool_location: Option<Point<f64, Logical>>,     // tablet tool in proximity, drawn with a cursor of its own
+    pub tool_cursor_status: CursorImageStatus,
+    pub cursor: Cursor,
+    pub keybinds: KeybindState,
+    pub layout: u32,        // index of the active keyboard layout
+    pub touch_slots: HashMap<TouchSlot, Option<(WlSurface, Point<f64, Logical>)>>,  // surface each touch point went down on
+    pub gesture: Option<ActiveGesture>,
+    pub broken_constraint: Option<WlSurface>,   // surface whose pointer constraint was broken by a keybind
+    pub libinput_devices: Vec<input::Device>,
+    pub keyboard_device: Option<String>,    // id of the keyboard typed on last
+    pub keyboard_device_config: Option<usize>,  // device entry whose keymap is active, the global one if unset
+    pub virtual_input: bool,    // set while input synthesized by a client is handled
+    pub input_method_popups: Vec<PopupSurface>,
+    pub persisted_selections: PersistedSelections,
+    pub drag: Option<Drag>,     // drag and drop in progress
+    pub ipc_events: Vec<String>     // broadcast to socket subscribers on next loop iteration
 }
 
 pub struct TopLevelWindows {
+    pub pending: Vec<ToplevelSurface>,      // created but not committed yet, they are mapped on their initial commit
     pub toplevels: VecDeque<ToplevelSurface>,
-    pub focused: Option<ToplevelSurface>
+    pub floating: Vec<ToplevelSurface>,     // stacking order, last one is on top
+    pub focused: Option<ToplevelSurface>,
+    pub properties: HashMap<WlSurface, WindowProperties>,
+    pub scratchpad: Vec<ToplevelSurface>,   // hidden scratchpad windows
+    pub named_scratchpads: HashMap<String, ToplevelSurface>,
+    pub pending_scratchpads: Vec<(String, Instant)>    // named scratchpads whose command was started, with the start time
+}
+
+#[derive(Debug)]
+pub struct WindowProperties {
+    pub location: Point<i32, Logical>,
+    pub size: Option<Size<i32, Logical>>,
+    pub opacity: f32,
+    pub no_focus: bool,
+    pub scratchpad: bool,
+    pub matched_rules: Vec<String>,
+    pub layout: Option<u32>     // last keyboard layout used in the window
+}
+
+impl Default for WindowProperties {
+    fn default() -> Self {
+        Self {
+            location: (0, 0).into(),
+            size: None,
+            opacity: 1.0,
+            no_focus: false,
+            scratchpad: false,
+            matched_rules: vec![],
+            layout: None
+        }
+    }
 }
 
 
@@ -40,12 +103,85 @@ impl SeatHandler for Waycrust {
         &mut self.seat_state
     }
 
-    fn focus_changed(&mut self, _seat: &Seat<Self>, _focused: Option<&WlSurface>) {}
-    fn cursor_image(&mut self, _seat: &Seat<Self>, _image: smithay::input::pointer::CursorImageStatus) {}
+    fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&WlSurface>) {
+        // selections are offered to the client with keyboard focus
+        let client = focused.and_then(|s| self.display_handle.get_client(s.id()).ok());
+        set_data_device_focus(&self.display_handle, seat, client.clone());
+        set_primary_focus(&self.display_handle, seat, client);
+    }
+    fn cursor_image(&mut self, _seat: &Seat<Self>, image: CursorImageStatus) {
+        self.cursor_status = image;
+    }
+}
+
+impl TabletSeatHandler for Waycrust {
+    fn tablet_tool_image(&mut self, _tool: &TabletToolDescriptor, image: CursorImageStatus) {
+        self.tool_cursor_status = image;
+    }
+}
+
+impl PointerConstraintsHandler for Waycrust {
+    fn new_constraint(&mut self, surface: &WlSurface, pointer: &PointerHandle<Self>) {
+        // constraints of the surface under the pointer apply right away
+        if pointer.current_focus().as_ref() == Some(surface) {
+            if let Some((_, _, origin)) = self.surface_under(pointer.current_location()) {
+                self.activate_pointer_constraint(surface, pointer.current_location() - origin);
+            }
+        }
+    }
+
+    fn cursor_position_hint(&mut self, surface: &WlSurface, pointer: &PointerHandle<Self>, location: Point<f64, Logical>) {
+        // clients like games report where the locked pointer should reappear
+        if with_pointer_constraint(surface, pointer, |c| c.is_some_and(|c| c.is_active())) {
+            if let Some((_, _, origin)) = self.surface_under(pointer.current_location()) {
+                pointer.set_location(origin + location);
+            }
+        }
+    }
+}
+
+impl KeyboardShortcutsInhibitHandler for Waycrust {
+    fn keyboard_shortcuts_inhibit_state(&mut self) -> &mut KeyboardShortcutsInhibitState {
+        &mut self.keyboard_shortcuts_inhibit_state
+    }
+
+    fn new_inhibitor(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
+        if self.shortcuts_inhibit_allowed(inhibitor.wl_surface()) {
+            inhibitor.activate();
+        } else {
+            println!("keyboard shortcuts inhibitor refused");
+        }
+    }
 }
 
 impl SelectionHandler for Waycrust {
-    type SelectionUserData = ();
+    type SelectionUserData = SelectionData;
+
+    fn new_selection(&mut self, ty: SelectionTarget, source: Option<SelectionSource>, seat: Seat<Self>) {
+        self.selection_changed(ty, source, &seat);
+    }
+
+    fn send_selection(&mut self, _ty: SelectionTarget, mime_type: String, fd: OwnedFd, _seat: Seat<Self>, user_data: &SelectionData) {
+        self.send_persisted_selection(mime_type, fd, user_data);
+    }
+}
+
+impl PrimarySelectionHandler for Waycrust {
+    fn primary_selection_state(&mut self) -> &mut PrimarySelectionState {
+        &mut self.primary_selection_state
+    }
+}
+
+impl wlr_data_control::DataControlHandler for Waycrust {
+    fn data_control_state(&mut self) -> &mut WlrDataControlState {
+        &mut self.wlr_data_control_state
+    }
+}
+
+impl ext_data_control::DataControlHandler for Waycrust {
+    fn data_control_state(&mut self) -> &mut ExtDataControlState {
+        &mut self.ext_data_control_state
+    }
 }
 
 impl DataDeviceHandler for Waycrust {
@@ -54,8 +190,6 @@ impl DataDeviceHandler for Waycrust {
     }
 }
 
-impl WaylandDndGrabHandler for Waycrust {}
-
 impl CompositorHandler for Waycrust {
     fn compositor_state(&mut self) -> &mut CompositorState {
         &mut self.compositor_state
@@ -67,6 +201,8 @@ impl CompositorHandler for Waycrust {
 
     fn commit(&mut self, surface: &WlSurface) {
         on_commit_buffer_handler::<Self>(surface);
+        self.toplevel_committed(surface);
+        self.drag_icon_committed(surface);
     }
 }
 
@@ -83,7 +219,25 @@ impl BufferHandler for Waycrust {
 #[derive(Default)]
 pub struct ClientState {
     compositor_state: CompositorClientState,
+    privileged: AtomicBool,
+    disconnected: AtomicBool
+}
+
+impl ClientState {
+    pub fn set_privileged(&self, privileged: bool) {
+        self.privileged.store(privileged, Ordering::Relaxed);
+    }
 }
+
+/// Clients allowed to use protocols that act on behalf of the user, like synthesizing input.
+pub fn client_privileged(client: &Client) -> bool {
+    client.get_data::<ClientState>().is_some_and(|c| c.privileged.load(Ordering::Relaxed))
+}
+
+pub fn client_disconnected(client: &Client) -> bool {
+    client.get_data::<ClientState>().is_some_and(|c| c.disconnected.load(Ordering::Relaxed))
+}
+
 impl ClientData for ClientState {
     fn initialized(&self, _client_id: ClientId) {
         println!("initialized");
@@ -91,5 +245,6 @@ impl ClientData for ClientState {
 
     fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {
         println!("disconnected");
+        self.disconnected.store(true, Ordering::Relaxed);
     }
 }
\ No newline at end of file
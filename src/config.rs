@@ -1,32 +1,42 @@
+use std::{collections::HashMap, path::PathBuf};
+
 use serde::{Deserialize, Serialize};
-use smithay::input::keyboard::XkbConfig;
-use xkbcommon::xkb::Keysym;
+use smithay::{backend::input::{Device, DeviceCapability}, input::keyboard::XkbConfig};
+use xkbcommon::xkb::{Keycode, Keysym};
+
+use crate::handlers::input::gestures::GestureKind;
 
+/// Key with modifiers, matched by keysym, by keycode or by both.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct KeybindShortcut {
-    #[serde(with = "keysym_serde")]
-    pub key: Keysym,
-    #[serde(deserialize_with = "modifier_mask_serde::deserialize")]
+    #[serde(with = "keysym_option_serde")]
     #[serde(default)]
-    pub modifiers: u8
-}
-
-pub trait ToMask {
-    fn to_mask(&self) -> u8;
-}
-
-impl ToMask for [KeyboardModifier] {
-    fn to_mask(&self) -> u8 {
-        self.iter().fold(0u8, |acc, m| acc | (*m as u8))
-    }
+    pub key: Option<Keysym>,
+    /// Physical key by evdev name or code, independent of the layout.
+    #[serde(with = "keycode_option_serde")]
+    #[serde(default)]
+    pub keycode: Option<Keycode>,
+    #[serde(default)]
+    pub modifiers: Vec<KeyboardModifier>
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyboardModifier {
-    Alt =   1 << 0,
-    Ctrl =  1 << 1,
-    Shift = 1 << 2,
-    Logo =  1 << 3
+    Alt,
+    Ctrl,
+    Shift,
+    Logo,
+    CapsLock,
+    NumLock,
+    AltGr,      // ISO_Level3_Shift
+    Hyper,
+    Mod1,
+    Mod2,
+    Mod3,
+    Mod4,
+    Mod5,
+    /// Any other modifier by its name in the keymap.
+    Custom(String)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -35,40 +45,591 @@ pub enum KeybindAction {
     Exec(String),
     CycleNext,
     CyclePrev,
+    MoveToScratchpad,
+    ToggleScratchpad,
+    Scratchpad(String),
+    EnterMode(String),
+    ExitMode,
+    NextLayout,
+    PrevLayout,
+    /// Layout by its name in `keyboard.layout` or by index.
+    SetLayout(String),
+    /// Releases a pointer locked or confined by the focused client.
+    BreakPointerConstraint,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Keybind {
-    pub shortcut: KeybindShortcut,
+    /// Either a single shortcut or a sequence of them pressed one after another.
+    #[serde(deserialize_with = "shortcut_sequence_serde::deserialize")]
+    pub shortcut: Vec<KeybindShortcut>,
+    pub action: KeybindAction,
+    /// Fire when the key is released without other keys pressed in the meantime, e.g. a lone Logo tap.
+    #[serde(default)]
+    pub on_release: bool,
+    /// Fire again while the key is held.
+    #[serde(default)]
+    pub repeat: bool,
+    /// Forward the key to the focused client after running the action.
+    #[serde(default)]
+    pub pass_through: bool,
+    /// Keep working while keybinds are inhibited.
+    #[serde(default)]
+    pub locked: bool
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MouseBind {
+    pub trigger: MouseTrigger,
+    #[serde(default)]
+    pub modifiers: Vec<KeyboardModifier>,
     pub action: KeybindAction
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseTrigger {
+    /// Evdev button code or name like BTN_MIDDLE.
+    Button(#[serde(with = "button_serde")] u32),
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight
+}
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GestureBind {
+    pub gesture: Gesture,
+    pub action: KeybindAction
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Gesture {
+    Swipe {
+        fingers: u32,
+        direction: SwipeDirection
+    },
+    Pinch {
+        fingers: u32,
+        direction: PinchDirection
+    },
+    Hold {
+        fingers: u32
+    }
+}
+
+impl Gesture {
+    pub fn kind(&self) -> GestureKind {
+        match self {
+            Gesture::Swipe { .. } => GestureKind::Swipe,
+            Gesture::Pinch { .. } => GestureKind::Pinch,
+            Gesture::Hold { .. } => GestureKind::Hold
+        }
+    }
+
+    pub fn fingers(&self) -> u32 {
+        match *self {
+            Gesture::Swipe { fingers, .. } | Gesture::Pinch { fingers, .. } | Gesture::Hold { fingers } => fingers
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwipeDirection {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PinchDirection {
+    In,
+    Out
+}
+
+/// Named set of keybinds used instead of the top level ones while the mode is active.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BindingMode {
+    #[serde(default)]
+    pub keybinds: Vec<Keybind>,
+    #[serde(default)]
+    pub mouse_binds: Vec<MouseBind>,
+    /// Milliseconds without a key press after which the default mode is restored.
+    #[serde(default)]
+    pub timeout: Option<u64>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub keybinds: Vec<Keybind>,
     #[serde(default)]
+    pub mouse_binds: Vec<MouseBind>,
+    /// Touchpad gestures, only reported by the libinput backend.
+    #[serde(default)]
+    pub gesture_binds: Vec<GestureBind>,
+    #[serde(default)]
+    pub modes: HashMap<String, BindingMode>,
+    /// Milliseconds to wait for the next key of a keybind sequence.
+    #[serde(default = "default_chord_timeout")]
+    pub chord_timeout: u64,
+    #[serde(default)]
     pub keyboard: KeyboardConfig,
     #[serde(default)]
-    pub remaps: Vec<KeyboardRemap>
+    pub remaps: Vec<KeyboardRemap>,
+    #[serde(default)]
+    pub window_rules: Vec<WindowRule>,
+    #[serde(default)]
+    pub scratchpads: Vec<Scratchpad>,
+    #[serde(default)]
+    pub focus: FocusConfig,
+    #[serde(default)]
+    pub pointer: PointerConfig,
+    #[serde(default)]
+    pub cursor: CursorConfig,
+    #[serde(default)]
+    pub touch: TouchConfig,
+    #[serde(default)]
+    pub tablet: TabletConfig,
+    #[serde(default)]
+    pub shortcuts_inhibit: ShortcutsInhibitConfig,
+    /// Settings for matching input devices, later entries override earlier ones.
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+    /// Absolute paths of executables whose clients may synthesize input or access the clipboard without
+    /// focus. Clients are checked when they connect, so changes on reload only affect new ones.
+    #[serde(default, deserialize_with = "absolute_paths_serde::deserialize")]
+    pub privileged_clients: Vec<PathBuf>,
+    #[serde(default)]
+    pub virtual_input: VirtualInputConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    /// Read input devices through libinput instead of the window, needs a seat session.
+    #[serde(default)]
+    pub libinput: bool
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct KeyboardRemap {
-    #[serde(with = "keysym_serde")]
-    pub from: Keysym,
-    #[serde(with = "keysym_serde")]
-    pub into: Keysym
+pub struct ShortcutsInhibitConfig {
+    /// App ids of clients allowed to inhibit keybinds, empty allows every client.
+    #[serde(default)]
+    pub allow: Vec<Matcher>,
+    /// Shortcut toggling the inhibitor of the focused window, it's never inhibited.
+    #[serde(default = "default_inhibit_escape")]
+    pub escape: KeybindShortcut
+}
+
+fn default_inhibit_escape() -> KeybindShortcut {
+    KeybindShortcut::new(Keysym::Escape, vec![KeyboardModifier::Logo])
+}
+
+impl Default for ShortcutsInhibitConfig {
+    fn default() -> Self {
+        Self {
+            allow: vec![],
+            escape: default_inhibit_escape()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TabletConfig {
+    /// Output area (x, y, width, height) the whole tablet maps to, the full output if unset.
+    #[serde(default)]
+    pub region: Option<(i32, i32, i32, i32)>
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ClipboardConfig {
+    /// Keep a copy of the clipboard, so it's still there after the app that copied it closes. Only its
+    /// text and one image format are kept.
+    #[serde(default)]
+    pub persist: bool,
+    /// Same for the middle click selection.
+    #[serde(default)]
+    pub persist_primary: bool
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct VirtualInputConfig {
+    /// Apply remaps, keybinds and mouse binds to input from virtual keyboards and pointers too.
+    #[serde(default)]
+    pub bindings: bool
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TouchConfig {
+    /// Output area (x, y, width, height) the whole touch surface maps to, the full output if unset.
+    #[serde(default)]
+    pub region: Option<(i32, i32, i32, i32)>
+}
+
+/// Input device settings, everything except `keyboard` needs the libinput backend.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    #[serde(default)]
+    pub name: Option<Matcher>,
+    #[serde(default)]
+    pub device_type: Option<DeviceType>,
+    #[serde(default)]
+    pub accel_profile: Option<AccelProfile>,
+    /// From -1.0 (slowest) to 1.0 (fastest).
+    #[serde(default)]
+    pub accel_speed: Option<f64>,
+    #[serde(default)]
+    pub tap_to_click: Option<bool>,
+    #[serde(default)]
+    pub natural_scroll: Option<bool>,
+    #[serde(default)]
+    pub left_handed: Option<bool>,
+    #[serde(default)]
+    pub disable_while_typing: Option<bool>,
+    #[serde(default)]
+    pub scroll_method: Option<ScrollMethod>,
+    /// First two rows of the matrix mapping touch and tablet coordinates.
+    #[serde(default)]
+    pub calibration_matrix: Option<[f32; 6]>,
+    /// Keymap used while typing on this keyboard instead of the global one.
+    #[serde(default)]
+    pub keyboard: Option<KeyboardConfig>
+}
+
+impl DeviceConfig {
+    /// Entry without any matcher applies to every device.
+    pub fn matches<D: Device>(&self, device: &D) -> bool {
+        self.name.as_ref().is_none_or(|m| m.matches(&device.name()))
+            && self.device_type.is_none_or(|t| t.matches(device))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DeviceType {
+    Keyboard,
+    Pointer,
+    Touchpad,
+    Touch,
+    Tablet
+}
+
+impl DeviceType {
+    fn matches<D: Device>(self, device: &D) -> bool {
+        // touchpads are the pointers reporting gestures
+        match self {
+            DeviceType::Keyboard => device.has_capability(DeviceCapability::Keyboard),
+            DeviceType::Pointer => device.has_capability(DeviceCapability::Pointer) && !device.has_capability(DeviceCapability::Gesture),
+            DeviceType::Touchpad => device.has_capability(DeviceCapability::Pointer) && device.has_capability(DeviceCapability::Gesture),
+            DeviceType::Touch => device.has_capability(DeviceCapability::Touch),
+            DeviceType::Tablet => device.has_capability(DeviceCapability::TabletTool)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum AccelProfile {
+    Flat,
+    Adaptive
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ScrollMethod {
+    NoScroll,
+    TwoFinger,
+    Edge,
+    OnButtonDown
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CursorConfig {
+    #[serde(default = "default_cursor_theme")]
+    pub theme: String,
+    #[serde(default = "default_cursor_size")]
+    pub size: u32,
+    #[serde(default)]
+    pub hidden: bool
+}
+
+fn default_cursor_theme() -> String {
+    std::env::var("XCURSOR_THEME").unwrap_or_else(|_| "default".to_string())
+}
+
+fn default_cursor_size() -> u32 {
+    std::env::var("XCURSOR_SIZE").ok().and_then(|s| s.parse().ok()).unwrap_or(24)
+}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        Self {
+            theme: default_cursor_theme(),
+            size: default_cursor_size(),
+            hidden: false
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PointerConfig {
+    #[serde(default)]
+    pub natural_scroll: bool,
+    #[serde(default = "default_scroll_factor")]
+    pub vertical_scroll_factor: f64,
+    #[serde(default = "default_scroll_factor")]
+    pub horizontal_scroll_factor: f64
+}
+
+fn default_scroll_factor() -> f64 {
+    1.0
+}
+
+impl Default for PointerConfig {
+    fn default() -> Self {
+        Self {
+            natural_scroll: false,
+            vertical_scroll_factor: default_scroll_factor(),
+            horizontal_scroll_factor: default_scroll_factor()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FocusConfig {
+    #[serde(default)]
+    pub policy: FocusPolicy,
+    #[serde(default)]
+    pub raise_on_focus: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum FocusPolicy {
+    #[default]
+    ClickToFocus,
+    FollowsMouse,   // focus follows the pointer, empty space clears focus
+    Sloppy          // like FollowsMouse, but focus stays when pointer leaves windows
+}
+
+fn default_chord_timeout() -> u64 {
+    1000
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keybinds: vec![],
+            mouse_binds: vec![],
+            gesture_binds: vec![],
+            modes: HashMap::new(),
+            chord_timeout: default_chord_timeout(),
+            keyboard: Default::default(),
+            remaps: vec![],
+            window_rules: vec![],
+            scratchpads: vec![],
+            focus: Default::default(),
+            pointer: Default::default(),
+            cursor: Default::default(),
+            touch: Default::default(),
+            tablet: Default::default(),
+            shortcuts_inhibit: Default::default(),
+            devices: vec![],
+            privileged_clients: vec![],
+            virtual_input: Default::default(),
+            clipboard: Default::default(),
+            libinput: false
+        }
+    }
+}
+
+/// Named scratchpad, its window is spawned with `exec` on first use and claimed by `app_id`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scratchpad {
+    pub name: String,
+    pub exec: String,
+    pub app_id: Matcher
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum KeyboardRemap {
+    /// Physical key into another one (by evdev name or code), applied before xkb so clients get the new key too.
+    Key {
+        #[serde(with = "keycode_serde")]
+        from: Keycode,
+        #[serde(with = "keycode_serde")]
+        into: Keycode
+    },
+    /// Keysym produced by xkb into another one. The key is swapped for one producing the target keysym
+    /// with the modifiers held, so clients get it too. Left alone if no key of the layout produces it.
+    Keysym {
+        #[serde(with = "keysym_serde")]
+        from: Keysym,
+        #[serde(with = "keysym_serde")]
+        into: Keysym
+    },
+    /// Keys of one modifier into keys of another, e.g. CapsLock into Ctrl.
+    Modifier {
+        from: RemapModifier,
+        into: RemapModifier
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RemapModifier {
+    CapsLock,
+    Ctrl,
+    Alt,
+    AltGr,
+    Shift,
+    Logo
+}
+
+impl RemapModifier {
+    /// Evdev codes of keys producing the modifier, left one first.
+    fn keys(self) -> &'static [u32] {
+        match self {
+            RemapModifier::CapsLock => &[58],
+            RemapModifier::Ctrl => &[29, 97],
+            RemapModifier::Alt => &[56],
+            RemapModifier::AltGr => &[100],
+            RemapModifier::Shift => &[42, 54],
+            RemapModifier::Logo => &[125, 126]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyboardConfig {
     pub rules: String,
     pub model: String,
+    /// Single layout or a list of them, switched with the layout actions.
+    #[serde(deserialize_with = "comma_list_serde::deserialize")]
     pub layout: String,
+    /// Variants for the layouts, in the same order.
+    #[serde(deserialize_with = "comma_list_serde::deserialize")]
     pub variant: String,
-    pub options: Option<String>
+    pub options: Option<String>,
+    /// Remember the layout of every window and switch back to it on focus.
+    #[serde(default)]
+    pub per_window_layout: bool,
+    /// Milliseconds before a held key starts repeating.
+    #[serde(default = "default_repeat_delay", deserialize_with = "non_negative_serde::deserialize")]
+    pub repeat_delay: i32,
+    /// Repeats per second, 0 disables key repeat.
+    #[serde(default = "default_repeat_rate", deserialize_with = "non_negative_serde::deserialize")]
+    pub repeat_rate: i32,
+    /// Lock states set on startup.
+    #[serde(default)]
+    pub numlock: bool,
+    #[serde(default)]
+    pub capslock: bool
+}
+
+fn default_repeat_delay() -> i32 {
+    200
+}
+
+fn default_repeat_rate() -> i32 {
+    200
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            rules: String::new(),
+            model: String::new(),
+            layout: String::new(),
+            variant: String::new(),
+            options: None,
+            per_window_layout: false,
+            repeat_delay: default_repeat_delay(),
+            repeat_rate: default_repeat_rate(),
+            numlock: false,
+            capslock: false
+        }
+    }
+}
+
+/// Pattern compiled when the config is loaded, so invalid ones are reported right away.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(try_from = "MatcherPattern", into = "MatcherPattern")]
+pub enum Matcher {
+    Glob(String, regex::Regex),
+    Regex(regex::Regex)
+}
+
+/// How a matcher is written in the config.
+#[derive(Serialize, Deserialize)]
+enum MatcherPattern {
+    Glob(String),
+    Regex(String)
+}
+
+impl TryFrom<MatcherPattern> for Matcher {
+    type Error = regex::Error;
+
+    fn try_from(pattern: MatcherPattern) -> Result<Self, Self::Error> {
+        Ok(match pattern {
+            MatcherPattern::Glob(glob) => {
+                let regex = regex::Regex::new(&glob_to_regex(&glob))?;
+                Matcher::Glob(glob, regex)
+            }
+            MatcherPattern::Regex(regex) => Matcher::Regex(regex::Regex::new(&regex)?)
+        })
+    }
+}
+
+impl From<Matcher> for MatcherPattern {
+    fn from(matcher: Matcher) -> Self {
+        match matcher {
+            Matcher::Glob(glob, _) => MatcherPattern::Glob(glob),
+            Matcher::Regex(regex) => MatcherPattern::Regex(regex.as_str().to_string())
+        }
+    }
+}
+
+impl Matcher {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Matcher::Glob(_, regex) | Matcher::Regex(regex) => regex.is_match(value)
+        }
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])))
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WindowRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub app_id: Option<Matcher>,
+    #[serde(default)]
+    pub title: Option<Matcher>,
+    #[serde(default)]
+    pub floating: Option<bool>,
+    #[serde(default)]
+    pub fullscreen: Option<bool>,
+    #[serde(default)]
+    pub size: Option<(i32, i32)>,
+    #[serde(default)]
+    pub opacity: Option<f32>,
+    #[serde(default)]
+    pub no_focus: Option<bool>
+}
+
+impl WindowRule {
+    /// Rule without any matcher applies to every window.
+    pub fn matches(&self, app_id: &str, title: &str) -> bool {
+        self.app_id.as_ref().is_none_or(|m| m.matches(app_id))
+            && self.title.as_ref().is_none_or(|m| m.matches(title))
+    }
 }
 
 impl<'a> From<&'a KeyboardConfig> for XkbConfig<'a> {
@@ -84,18 +645,49 @@ impl<'a> From<&'a KeyboardConfig> for XkbConfig<'a> {
 }
 
 
-impl KeybindShortcut {
-    pub fn new_verbose(key: impl Into<Keysym>, alt: bool, ctrl: bool, shift: bool, logo: bool) -> Self {
-        Self {
-            key: key.into(),
-            modifiers: alt as u8 | (ctrl as u8) << 1 | (shift as u8) << 2 | (logo as u8) << 3
+// xkb keycodes are evdev codes offset by 8
+const EVDEV_OFFSET: u32 = 8;
+
+impl Config {
+    /// Keybinds of a binding mode, the top level ones for the default mode.
+    pub fn mode_keybinds(&self, mode: Option<&str>) -> &[Keybind] {
+        match mode.and_then(|m| self.modes.get(m)) {
+            Some(mode) => &mode.keybinds,
+            None => &self.keybinds
         }
     }
 
+    /// Applies first matching key or modifier remap. Remaps don't chain, so two
+    /// opposite entries swap the keys.
+    pub fn remap_keycode(&self, key: Keycode) -> Keycode {
+        for remap in &self.remaps {
+            match remap {
+                KeyboardRemap::Key { from, into } if *from == key => return *into,
+                KeyboardRemap::Modifier { from, into }
+                    if from.keys().iter().any(|k| k + EVDEV_OFFSET == key.raw()) => {
+                    return Keycode::new(into.keys()[0] + EVDEV_OFFSET)
+                }
+                _ => {}
+            }
+        }
+        key
+    }
+
+    /// Target of the first keysym remap of `sym`, if there is one.
+    pub fn remap_keysym(&self, sym: Keysym) -> Option<Keysym> {
+        self.remaps.iter().find_map(|remap| match remap {
+            KeyboardRemap::Keysym { from, into } if *from == sym => Some(*into),
+            _ => None
+        })
+    }
+}
+
+impl KeybindShortcut {
     pub fn new(key: impl Into<Keysym>, modifiers: Vec<KeyboardModifier>) -> Self {
         Self {
-            key: key.into(),
-            modifiers: modifiers.iter().fold(0u8, |acc, m| acc | (*m as u8))
+            key: Some(key.into()),
+            keycode: None,
+            modifiers
         }
     }
 }
@@ -191,15 +783,360 @@ mod keysym_serde {
 
 }
 
-mod modifier_mask_serde {
-    use serde::{Deserializer, Deserialize};
-    use crate::config::KeyboardModifier;
+mod keysym_option_serde {
+    use serde::{Serializer, Deserializer};
+    use xkbcommon::xkb::Keysym;
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<u8, D::Error>
+    pub fn serialize<S>(key: &Option<Keysym>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match key {
+            Some(key) => super::keysym_serde::serialize(key, serializer),
+            None => serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Keysym>, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let mods: Vec<KeyboardModifier> = Vec::deserialize(deserializer)?;
-        Ok(mods.iter().fold(0u8, |acc, m| acc | (*m as u8)))
+        super::keysym_serde::deserialize(deserializer).map(Some)
     }
-}
\ No newline at end of file
+}
+
+mod keycode_option_serde {
+    use serde::{Serializer, Deserializer};
+    use xkbcommon::xkb::Keycode;
+
+    pub fn serialize<S>(key: &Option<Keycode>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match key {
+            Some(key) => super::keycode_serde::serialize(key, serializer),
+            None => serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Keycode>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::keycode_serde::deserialize(deserializer).map(Some)
+    }
+}
+
+mod keycode_serde {
+    use serde::{Serializer, Deserializer, de::{Error, Visitor}};
+    use xkbcommon::xkb::Keycode;
+    use std::{fmt, str::FromStr};
+
+    use super::EVDEV_OFFSET;
+
+    pub fn serialize<S>(key: &Keycode, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u32(key.raw() - EVDEV_OFFSET)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Keycode, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(KeycodeVisitor)
+    }
+
+    struct KeycodeVisitor;
+
+    impl<'de> Visitor<'de> for KeycodeVisitor {
+        type Value = Keycode;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an evdev key code or name like KEY_CAPSLOCK")
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(Keycode::new(v as u32 + EVDEV_OFFSET))
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error, {
+            evdev::KeyCode::from_str(v)
+                .map(|key| Keycode::new(key.code() as u32 + EVDEV_OFFSET))
+                .map_err(|_| Error::custom("Invalid evdev key name"))
+        }
+    }
+}
+
+mod button_serde {
+    use serde::{Serializer, Deserializer, de::{Error, Visitor}};
+    use std::{fmt, str::FromStr};
+
+    pub fn serialize<S>(button: &u32, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u32(*button)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ButtonVisitor)
+    }
+
+    struct ButtonVisitor;
+
+    impl<'de> Visitor<'de> for ButtonVisitor {
+        type Value = u32;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "an evdev button code or name like BTN_LEFT")
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(v as u32)
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error, {
+            evdev::KeyCode::from_str(v)
+                .map(|button| button.code() as u32)
+                .map_err(|_| Error::custom("Invalid evdev button name"))
+        }
+    }
+}
+
+mod comma_list_serde {
+    use serde::{Deserializer, Deserialize, de::{SeqAccess, Visitor, value::SeqAccessDeserializer}};
+    use std::fmt;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ListVisitor)
+    }
+
+    // xkb takes several layouts or variants as one comma separated string
+    struct ListVisitor;
+
+    impl<'de> Visitor<'de> for ListVisitor {
+        type Value = String;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a string or a list of strings")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let list: Vec<String> = Vec::deserialize(SeqAccessDeserializer::new(seq))?;
+            Ok(list.join(","))
+        }
+    }
+}
+
+mod non_negative_serde {
+    use serde::{Deserializer, Deserialize, de::Error};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<i32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = i32::deserialize(deserializer)?;
+        if value < 0 {
+            return Err(Error::custom("value can't be negative"))
+        }
+        Ok(value)
+    }
+}
+
+mod absolute_paths_serde {
+    use serde::{Deserializer, Deserialize, de::Error};
+    use std::path::PathBuf;
+
+    // relative paths would match whatever binary of that name is run from the right directory
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let paths = Vec::<PathBuf>::deserialize(deserializer)?;
+        match paths.iter().find(|p| !p.is_absolute()) {
+            Some(path) => Err(Error::custom(format!("{} is not an absolute path", path.display()))),
+            None => Ok(paths)
+        }
+    }
+}
+
+mod shortcut_sequence_serde {
+    use serde::{Deserializer, Deserialize, de::{Error, MapAccess, SeqAccess, Visitor, value::{MapAccessDeserializer, SeqAccessDeserializer}}};
+    use crate::config::KeybindShortcut;
+    use std::fmt;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<KeybindShortcut>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(SequenceVisitor)
+    }
+
+    struct SequenceVisitor;
+
+    impl<'de> Visitor<'de> for SequenceVisitor {
+        type Value = Vec<KeybindShortcut>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a shortcut or a list of shortcuts")
+        }
+
+        fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            KeybindShortcut::deserialize(MapAccessDeserializer::new(map)).map(|s| vec![s])
+        }
+
+        fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let sequence: Vec<KeybindShortcut> = Vec::deserialize(SeqAccessDeserializer::new(seq))?;
+            if sequence.is_empty() {
+                return Err(Error::custom("Empty shortcut sequence"))
+            }
+            Ok(sequence)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str) -> Result<Matcher, ron::error::SpannedError> {
+        ron::from_str(pattern)
+    }
+
+    #[test]
+    fn glob_matches_whole_value() {
+        let glob = matcher(r#"Glob("org.*.Terminal?")"#).unwrap();
+        assert!(glob.matches("org.gnome.Terminal2"));
+        assert!(!glob.matches("org.gnome.Terminal"));
+        assert!(!glob.matches("xorg.gnome.Terminal2"));
+    }
+
+    #[test]
+    fn glob_escapes_regex_characters() {
+        let glob = matcher(r#"Glob("a.b+")"#).unwrap();
+        assert!(glob.matches("a.b+"));
+        assert!(!glob.matches("axbb"));
+    }
+
+    #[test]
+    fn regex_matches_anywhere() {
+        let regex = matcher(r#"Regex("fire(fox)?")"#).unwrap();
+        assert!(regex.matches("org.mozilla.firefox"));
+        assert!(!regex.matches("chromium"));
+    }
+
+    #[test]
+    fn invalid_pattern_fails_to_load() {
+        assert!(matcher(r#"Regex("(unclosed")"#).is_err());
+    }
+
+    #[test]
+    fn window_rule_without_matchers_applies_to_every_window() {
+        let rule: WindowRule = ron::from_str("(opacity: Some(0.9))").unwrap();
+        assert!(rule.matches("anything", "at all"));
+
+        let rule: WindowRule = ron::from_str(r#"(app_id: Some(Glob("mpv")), title: Some(Regex("^Video")))"#).unwrap();
+        assert!(rule.matches("mpv", "Video - clip.mkv"));
+        assert!(!rule.matches("mpv", "Audio"));
+    }
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config: Config = ron::from_str("()").unwrap();
+        assert!(config.keybinds.is_empty());
+        assert_eq!(config.chord_timeout, default_chord_timeout());
+        assert_eq!(config.keyboard.repeat_delay, default_repeat_delay());
+    }
+
+    #[test]
+    fn negative_repeat_fails_to_load() {
+        let keyboard = |repeat: &str| ron::from_str::<KeyboardConfig>(
+            &format!(r#"(rules: "", model: "", layout: "us", variant: "", options: None, {})"#, repeat)
+        );
+        assert!(keyboard("repeat_delay: 300, repeat_rate: 0").is_ok());
+        assert!(keyboard("repeat_delay: -1").is_err());
+        assert!(keyboard("repeat_rate: -25").is_err());
+    }
+
+    #[test]
+    fn privileged_clients_need_absolute_paths() {
+        assert!(ron::from_str::<Config>(r#"(privileged_clients: ["/usr/bin/wtype"])"#).is_ok());
+        assert!(ron::from_str::<Config>(r#"(privileged_clients: ["wtype"])"#).is_err());
+    }
+
+    fn remaps(remaps: &str) -> Config {
+        ron::from_str(&format!("(remaps: {})", remaps)).unwrap()
+    }
+
+    fn key(code: u32) -> Keycode {
+        Keycode::new(code + EVDEV_OFFSET)
+    }
+
+    #[test]
+    fn key_remap_by_name_and_code() {
+        let config = remaps(r#"[Key(from: "KEY_RIGHTALT", into: "KEY_COMPOSE"), Key(from: 30, into: 48)]"#);
+        assert_eq!(config.remap_keycode(key(100)), key(127));
+        assert_eq!(config.remap_keycode(key(30)), key(48));
+        assert_eq!(config.remap_keycode(key(31)), key(31));
+    }
+
+    #[test]
+    fn remaps_swap_without_chaining() {
+        let config = remaps(r#"[Key(from: "KEY_A", into: "KEY_B"), Key(from: "KEY_B", into: "KEY_A")]"#);
+        assert_eq!(config.remap_keycode(key(30)), key(48));
+        assert_eq!(config.remap_keycode(key(48)), key(30));
+    }
+
+    #[test]
+    fn modifier_remap_covers_both_sides() {
+        let config = remaps("[Modifier(from: Shift, into: Ctrl)]");
+        assert_eq!(config.remap_keycode(key(42)), key(29));
+        assert_eq!(config.remap_keycode(key(54)), key(29));
+        assert_eq!(config.remap_keycode(key(58)), key(58));
+    }
+
+    #[test]
+    fn keysym_remap_leaves_keycodes_alone() {
+        let config = remaps(r#"[Keysym(from: "Menu", into: "Super_R")]"#);
+        assert_eq!(config.remap_keysym(Keysym::Menu), Some(Keysym::Super_R));
+        assert_eq!(config.remap_keysym(Keysym::Super_R), None);
+        assert_eq!(config.remap_keycode(key(127)), key(127));
+    }
+
+    #[test]
+    fn shipped_config_parses() {
+        let config: Result<Config, _> = ron::from_str(include_str!("../config.ron"));
+        assert!(config.is_ok(), "{:?}", config.err());
+    }
+}
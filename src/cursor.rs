@@ -0,0 +1,94 @@
+use std::{collections::HashMap, io::Read};
+
+use smithay::{backend::{allocator::Fourcc, renderer::element::memory::MemoryRenderBuffer}, input::pointer::CursorIcon, utils::{Logical, Point, Transform}};
+use xcursor::{CursorTheme, parser::parse_xcursor};
+
+use crate::config::CursorConfig;
+
+
+
+pub struct CursorFrame {
+    pub buffer: MemoryRenderBuffer,
+    pub hotspot: Point<i32, Logical>,
+    delay: u32
+}
+
+/// Xcursor theme used for named cursors, icons are loaded on first use.
+pub struct Cursor {
+    theme: CursorTheme,
+    size: u32,
+    icons: HashMap<String, Vec<CursorFrame>>
+}
+
+impl Cursor {
+    pub fn load(config: &CursorConfig) -> Self {
+        Self {
+            theme: CursorTheme::load(&config.theme),
+            size: config.size,
+            icons: HashMap::new()
+        }
+    }
+
+    /// Returns the frame of an (possibly animated) icon that should be shown at `time`.
+    /// Themes name some shapes differently, so legacy names of the icon are tried
+    /// before falling back to the default cursor.
+    pub fn icon_frame(&mut self, icon: CursorIcon, time: u32) -> Option<&CursorFrame> {
+        let name = std::iter::once(icon.name())
+            .chain(icon.alt_names().iter().copied())
+            .chain(std::iter::once("default"))
+            .find(|name| self.ensure_loaded(name))?;
+        let frames = &self.icons[name];
+
+        let total: u32 = frames.iter().map(|f| f.delay).sum();
+        if total == 0 {
+            return frames.first()
+        }
+
+        let mut millis = time % total;
+        for frame in frames {
+            if millis < frame.delay {
+                return Some(frame)
+            }
+            millis -= frame.delay;
+        }
+        frames.first()
+    }
+
+    fn ensure_loaded(&mut self, name: &str) -> bool {
+        if !self.icons.contains_key(name) {
+            let frames = self.load_icon(name).unwrap_or_else(|| {
+                println!("cursor icon {} not found", name);
+                vec![]
+            });
+            self.icons.insert(name.to_string(), frames);
+        }
+        !self.icons[name].is_empty()
+    }
+
+    fn load_icon(&self, name: &str) -> Option<Vec<CursorFrame>> {
+        let path = self.theme.load_icon(name)?;
+        let mut data = vec![];
+        std::fs::File::open(path).ok()?.read_to_end(&mut data).ok()?;
+        let images = parse_xcursor(&data)?;
+
+        // themes ship every icon in several sizes, pick frames of the one closest to configured size
+        let nearest = images.iter().min_by_key(|image| (self.size as i32 - image.size as i32).abs())?;
+        let frames = images.iter()
+            .filter(|image| image.width == nearest.width && image.height == nearest.height)
+            .map(|image| CursorFrame {
+                buffer: MemoryRenderBuffer::from_slice(
+                    &image.pixels_rgba,
+                    Fourcc::Argb8888,
+                    (image.width as i32, image.height as i32),
+                    1,
+                    Transform::Normal,
+                    None
+                ),
+                hotspot: (image.xhot as i32, image.yhot as i32).into(),
+                delay: image.delay
+            })
+            .collect();
+
+        Some(frames)
+    }
+}
@@ -0,0 +1,97 @@
+use smithay::reexports::input::{self, Device, DeviceConfigResult};
+
+use crate::{compositor::Waycrust, config::{AccelProfile, KeyboardConfig, ScrollMethod}};
+
+
+
+impl Waycrust {
+    pub fn add_libinput_device(&mut self, mut device: Device) {
+        self.configure_libinput_device(&mut device);
+        self.libinput_devices.push(device);
+    }
+
+    pub fn remove_libinput_device(&mut self, device: &Device) {
+        self.libinput_devices.retain(|d| d != device);
+    }
+
+    /// Applies the device settings again to everything connected, after the config changed.
+    pub fn reconfigure_libinput_devices(&mut self) {
+        let mut devices = std::mem::take(&mut self.libinput_devices);
+        for device in devices.iter_mut() {
+            self.configure_libinput_device(device);
+        }
+        self.libinput_devices = devices;
+    }
+
+    fn configure_libinput_device(&self, device: &mut Device) {
+        let name = device.name().to_string();
+        for config in self.config.devices.iter() {
+            if !config.matches(&*device) {
+                continue
+            }
+            if let Some(profile) = config.accel_profile {
+                let profile = match profile {
+                    AccelProfile::Flat => input::AccelProfile::Flat,
+                    AccelProfile::Adaptive => input::AccelProfile::Adaptive
+                };
+                report(&name, "acceleration profile", device.config_accel_set_profile(profile));
+            }
+            if let Some(speed) = config.accel_speed {
+                report(&name, "acceleration speed", device.config_accel_set_speed(speed));
+            }
+            if let Some(enabled) = config.tap_to_click {
+                report(&name, "tap to click", device.config_tap_set_enabled(enabled));
+            }
+            if let Some(enabled) = config.natural_scroll {
+                report(&name, "natural scroll", device.config_scroll_set_natural_scroll_enabled(enabled));
+            }
+            if let Some(enabled) = config.left_handed {
+                report(&name, "left handed", device.config_left_handed_set(enabled));
+            }
+            if let Some(enabled) = config.disable_while_typing {
+                report(&name, "disable while typing", device.config_dwt_set_enabled(enabled));
+            }
+            if let Some(method) = config.scroll_method {
+                let method = match method {
+                    ScrollMethod::NoScroll => input::ScrollMethod::NoScroll,
+                    ScrollMethod::TwoFinger => input::ScrollMethod::TwoFinger,
+                    ScrollMethod::Edge => input::ScrollMethod::Edge,
+                    ScrollMethod::OnButtonDown => input::ScrollMethod::OnButtonDown
+                };
+                report(&name, "scroll method", device.config_scroll_set_method(method));
+            }
+            if let Some(matrix) = config.calibration_matrix {
+                report(&name, "calibration matrix", device.config_calibration_set_matrix(matrix));
+            }
+        }
+    }
+
+    /// Keymap of the keyboard typed on last, the global one unless a device entry has its own.
+    pub fn keyboard_config(&self) -> &KeyboardConfig {
+        self.keyboard_device_config
+            .and_then(|idx| self.config.devices.get(idx))
+            .and_then(|c| c.keyboard.as_ref())
+            .unwrap_or(&self.config.keyboard)
+    }
+
+    /// Switches the keymap when typing moves to a keyboard with a different one.
+    pub fn switch_keyboard_device<D: smithay::backend::input::Device>(&mut self, device: &D) {
+        let id = device.id();
+        if self.keyboard_device.as_ref() == Some(&id) {
+            return
+        }
+        self.keyboard_device = Some(id);
+
+        let config = self.config.devices.iter().rposition(|c| c.keyboard.is_some() && c.matches(device));
+        if config != self.keyboard_device_config {
+            self.keyboard_device_config = config;
+            self.apply_xkb_config();
+        }
+    }
+}
+
+fn report(device: &str, setting: &str, result: DeviceConfigResult) {
+    if let Err(err) = result {
+        println!("cannot set {} on {}: {:?}", setting, device, err);
+    }
+}
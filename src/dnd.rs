@@ -0,0 +1,185 @@
+use std::time::{Duration, Instant};
+
+use smithay::{
+    backend::{input::TouchSlot, renderer::utils::with_renderer_surface_state},
+    input::{Seat, dnd::{DnDGrab, DndGrabHandler, DndTarget, GrabType, Source}, pointer::{Focus, MotionEvent}, touch},
+    utils::{Clock, Logical, Monotonic, Point, SERIAL_COUNTER, Serial},
+    wayland::selection::data_device::WaylandDndGrabHandler
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use crate::compositor::Waycrust;
+
+// how long a drag has to rest on the left or right output edge to switch windows
+const EDGE_SWITCH_DELAY: Duration = Duration::from_millis(600);
+// width of the edge in pixels, a nested window rarely reports its very last column before the host pointer leaves it
+const EDGE_ZONE: i32 = 8;
+
+/// Input a drag follows.
+#[derive(Clone, Copy)]
+enum DragInput {
+    Pointer,
+    Touch(TouchSlot)
+}
+
+/// Drag and drop in progress.
+pub struct Drag {
+    pub icon: Option<WlSurface>,
+    pub icon_offset: Point<i32, Logical>,
+    input: DragInput,
+    touch_location: Point<f64, Logical>,    // last position of the touch point, touch drags only
+    edge_since: Option<Instant>
+}
+
+impl WaylandDndGrabHandler for Waycrust {
+    fn dnd_requested<S: Source>(&mut self, source: S, icon: Option<WlSurface>, seat: Seat<Self>, serial: Serial, type_: GrabType) {
+        match type_ {
+            GrabType::Pointer => {
+                let Some(pointer) = seat.get_pointer() else {
+                    return
+                };
+                let Some(start_data) = pointer.grab_start_data() else {
+                    return
+                };
+                self.drag = Some(Drag {
+                    icon,
+                    icon_offset: (0, 0).into(),
+                    input: DragInput::Pointer,
+                    touch_location: (0.0, 0.0).into(),
+                    edge_since: None
+                });
+                let grab = DnDGrab::new_pointer(&self.display_handle, start_data, source, seat);
+                pointer.set_grab(self, grab, serial, Focus::Keep);
+            }
+            GrabType::Touch => {
+                let Some(touch) = seat.get_touch() else {
+                    return
+                };
+                let Some(start_data) = touch.grab_start_data() else {
+                    return
+                };
+                self.drag = Some(Drag {
+                    icon,
+                    icon_offset: (0, 0).into(),
+                    input: DragInput::Touch(start_data.slot),
+                    touch_location: start_data.location,
+                    edge_since: None
+                });
+                let grab = DnDGrab::new_touch(&self.display_handle, start_data, source, seat);
+                touch.set_grab(self, grab, serial);
+            }
+        }
+    }
+}
+
+impl DndGrabHandler for Waycrust {
+    fn dropped(&mut self, target: Option<DndTarget<'_, Self>>, validated: bool, _seat: Seat<Self>, location: Point<f64, Logical>) {
+        self.drag = None;
+        // the window that accepted the drop gets focus, like after a click
+        if validated && target.is_some() {
+            if let Some((window, _, _)) = self.surface_under(location) {
+                if self.toplevels.focused.as_ref() != Some(&window) {
+                    self.focus_toplevel(Some(window));
+                }
+            }
+        }
+    }
+}
+
+impl Waycrust {
+    /// Moves the drag icon along with the offsets its client attaches buffers with.
+    pub fn drag_icon_committed(&mut self, surface: &WlSurface) {
+        let Some(drag) = self.drag.as_mut().filter(|d| d.icon.as_ref() == Some(surface)) else {
+            return
+        };
+        // the buffer handler already moved the delta from the surface attributes
+        let delta = with_renderer_surface_state(surface, |state| state.buffer_delta.take()).flatten();
+        if let Some(delta) = delta {
+            drag.icon_offset += delta;
+        }
+    }
+
+    /// Where the drag currently is, the icon is drawn there.
+    pub fn drag_location(&self) -> Option<Point<f64, Logical>> {
+        let drag = self.drag.as_ref()?;
+        match drag.input {
+            DragInput::Pointer => self.seat.get_pointer().map(|p| p.current_location()),
+            DragInput::Touch(_) => Some(drag.touch_location)
+        }
+    }
+
+    /// Follows the touch point a touch drag was started with.
+    pub fn drag_touch_moved(&mut self, slot: TouchSlot, location: Point<f64, Logical>) {
+        if let Some(drag) = self.drag.as_mut().filter(|d| matches!(d.input, DragInput::Touch(s) if s == slot)) {
+            drag.touch_location = location;
+        }
+    }
+
+    /// The grab can also end without a drop, e.g. when the client destroys the source.
+    fn drag_grab_active(&self) -> bool {
+        match self.drag.as_ref().map(|d| d.input) {
+            Some(DragInput::Pointer) => self.seat.get_pointer().is_some_and(|p| p.is_grabbed()),
+            Some(DragInput::Touch(_)) => self.seat.get_touch().is_some_and(|t| t.is_grabbed()),
+            None => false
+        }
+    }
+
+    /// Resting a drag near the left or right output edge cycles windows, so it can be dropped on a hidden one.
+    /// Called once per loop iteration, it also forgets drags whose grab ended.
+    pub fn check_drag_edge(&mut self) {
+        if self.drag.is_some() && !self.drag_grab_active() {
+            self.drag = None;
+        }
+        let (Some(location), Some(size)) = (self.drag_location(), self.size) else {
+            return
+        };
+        let Some(drag) = self.drag.as_mut() else {
+            return
+        };
+        let next = if location.x >= (size.w - EDGE_ZONE) as f64 {
+            true
+        } else if location.x < EDGE_ZONE as f64 {
+            false
+        } else {
+            drag.edge_since = None;
+            return
+        };
+
+        let since = *drag.edge_since.get_or_insert_with(Instant::now);
+        if since.elapsed() < EDGE_SWITCH_DELAY {
+            return
+        }
+        // keeps cycling while the drag stays on the edge
+        drag.edge_since = Some(Instant::now());
+        let input = drag.input;
+        if next {
+            self.next_toplevel();
+        } else {
+            self.previous_toplevel();
+        }
+
+        // the drag target is updated on motion, the window under the drag just changed
+        let under = self.surface_under(location).map(|(_, surface, loc)| (surface, loc));
+        let time = Duration::from(Clock::<Monotonic>::new().now()).as_millis() as u32;
+        match input {
+            DragInput::Pointer => {
+                let Some(pointer) = self.seat.get_pointer() else {
+                    return
+                };
+                pointer.motion(self, under, &MotionEvent {
+                    location,
+                    serial: SERIAL_COUNTER.next_serial(),
+                    time
+                });
+                pointer.frame(self);
+            }
+            DragInput::Touch(slot) => {
+                let Some(touch) = self.seat.get_touch() else {
+                    return
+                };
+                touch.motion(self, under, &touch::MotionEvent { slot, location, time });
+                touch.frame(self);
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use crate::{compositor::Waycrust, socket::Action};
+use crate::{comp_utils::spawn_command, compositor::Waycrust, rules::toplevel_identity, socket::Action};
 
-pub fn handle_actions(state: &mut Waycrust, action: Action) {
+pub fn handle_actions(state: &mut Waycrust, action: Action) -> Option<String> {
     match action {
         Action::Exit => {
             if let Some(ref toplevel) = state.toplevels.focused {
@@ -8,17 +8,61 @@ pub fn handle_actions(state: &mut Waycrust, action: Action) {
             }
         }
         Action::Exec(command) => {
-            let (command, args) = {
-                let mut iter = shlex::Shlex::new(&command);
-                let command = iter.next().unwrap_or_default().to_string();
-                let args: Vec<String> = iter.collect();
-                (command, args)
-            };
-
-            std::process::Command::new(command).args(args).spawn().ok();
+            spawn_command(&command);
         }
         Action::Flip => {
             state.flipped = !state.flipped;
         }
+        Action::Rules => {
+            let windows = state.toplevels.toplevels.iter()
+                .chain(state.toplevels.floating.iter())
+                .chain(state.toplevels.scratchpad.iter());
+            let lines: Vec<String> = windows.map(|top| {
+                let (app_id, title) = toplevel_identity(top);
+                let rules = state.toplevels.properties.get(top.wl_surface())
+                    .map(|p| p.matched_rules.join(", "))
+                    .unwrap_or_default();
+                let focused = if state.toplevels.focused.as_ref() == Some(top) { "*" } else { " " };
+                format!("{} app_id={:?} title={:?} rules=[{}]", focused, app_id, title, rules)
+            }).collect();
+            return Some(lines.join("\n"))
+        }
+        Action::Mode(Some(name)) => {
+            state.enter_mode(Some(name));
+        }
+        Action::Mode(None) => {
+            return Some(state.mode_name().to_string())
+        }
+        Action::Layout(Some(layout)) => {
+            match layout.as_str() {
+                "next" => state.next_layout(),
+                "prev" => state.previous_layout(),
+                name => state.select_layout(name)
+            }
+        }
+        Action::Layout(None) => {
+            return Some(state.layout_name())
+        }
+        Action::Repeat(Some((delay, rate))) => {
+            state.set_repeat_info(delay, rate);
+        }
+        Action::Repeat(None) => {
+            let keyboard = &state.config.keyboard;
+            return Some(format!("{} {}", keyboard.repeat_delay, keyboard.repeat_rate))
+        }
+        Action::Reload => {
+            state.reload_config();
+        }
+        Action::Gesture { kind, fingers, delta, scale } => {
+            // a real gesture is in progress, the synthetic one would get mixed into it
+            if state.gesture.is_some() {
+                return Some("busy".to_string())
+            }
+            let recognized = state.simulate_gesture(kind, fingers, delta.into(), scale);
+            return Some(recognized.map(|g| format!("{:?}", g)).unwrap_or_else(|| "none".to_string()))
+        }
+        Action::Subscribe => {}
     }
+
+    None
 }
\ No newline at end of file
@@ -0,0 +1,262 @@
+use smithay::{backend::input::{Event, GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent, GestureSwipeUpdateEvent, InputBackend}, input::pointer::{GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent, GesturePinchUpdateEvent as PinchUpdate, GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent as SwipeUpdate}, utils::{Logical, Point, SERIAL_COUNTER}};
+
+use crate::{compositor::Waycrust, config::{Gesture, PinchDirection, SwipeDirection}, handlers::input::keybinds::run_keybind_action};
+
+// distance a swipe has to travel and how much a pinch has to scale to count as a gesture bind
+const SWIPE_THRESHOLD: f64 = 50.0;
+const PINCH_THRESHOLD: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureKind {
+    Swipe,
+    Pinch,
+    Hold
+}
+
+/// Gesture in progress, recognized while it moves and again when it ends.
+pub struct ActiveGesture {
+    kind: GestureKind,
+    fingers: u32,
+    delta: Point<f64, Logical>,
+    scale: f64,
+    route: GestureRoute,
+    begin_time: u32,
+    held_back: Vec<HeldUpdate>      // updates of an undecided gesture, replayed if no bind takes it
+}
+
+/// Gestures of a kind and finger count some bind uses are held back until their direction is known.
+/// Only those that turn out to fire a bind are kept from clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GestureRoute {
+    Undecided,
+    Bound,
+    Forwarded
+}
+
+struct HeldUpdate {
+    delta: Point<f64, Logical>,
+    scale: f64,
+    rotation: f64,
+    time: u32
+}
+
+impl ActiveGesture {
+    fn recognize(&self) -> Option<Gesture> {
+        let fingers = self.fingers;
+        match self.kind {
+            GestureKind::Swipe => {
+                let (x, y) = (self.delta.x, self.delta.y);
+                if x.abs().max(y.abs()) < SWIPE_THRESHOLD {
+                    return None
+                }
+                let direction = match (x.abs() > y.abs(), x > 0.0, y > 0.0) {
+                    (true, true, _) => SwipeDirection::Right,
+                    (true, false, _) => SwipeDirection::Left,
+                    (false, _, true) => SwipeDirection::Down,
+                    (false, _, false) => SwipeDirection::Up
+                };
+                Some(Gesture::Swipe { fingers, direction })
+            }
+            GestureKind::Pinch => {
+                let direction = if self.scale < 1.0 - PINCH_THRESHOLD {
+                    PinchDirection::In
+                } else if self.scale > 1.0 + PINCH_THRESHOLD {
+                    PinchDirection::Out
+                } else {
+                    return None
+                };
+                Some(Gesture::Pinch { fingers, direction })
+            }
+            GestureKind::Hold => Some(Gesture::Hold { fingers })
+        }
+    }
+}
+
+impl Waycrust {
+    pub fn gesture_begin(&mut self, kind: GestureKind, fingers: u32, time: u32) {
+        let bindable = self.config.gesture_binds.iter().any(|b| b.gesture.kind() == kind && b.gesture.fingers() == fingers);
+        let mut gesture = ActiveGesture {
+            kind,
+            fingers,
+            delta: (0.0, 0.0).into(),
+            scale: 1.0,
+            route: if bindable { GestureRoute::Undecided } else { GestureRoute::Forwarded },
+            begin_time: time,
+            held_back: vec![]
+        };
+        if bindable {
+            // holds have no direction, they are decided right away
+            self.decide_gesture(&mut gesture);
+        } else {
+            self.send_gesture_begin(kind, fingers, time);
+        }
+        self.gesture = Some(gesture);
+    }
+
+    /// Scale is relative to the start of the gesture, rotation to the previous update.
+    pub fn gesture_update(&mut self, delta: Point<f64, Logical>, scale: f64, rotation: f64, time: u32) {
+        let Some(mut gesture) = self.gesture.take() else {
+            return
+        };
+        gesture.delta += delta;
+        gesture.scale = scale;
+        match gesture.route {
+            GestureRoute::Undecided => {
+                gesture.held_back.push(HeldUpdate { delta, scale, rotation, time });
+                self.decide_gesture(&mut gesture);
+            }
+            GestureRoute::Forwarded => self.send_gesture_update(gesture.kind, delta, scale, rotation, time),
+            GestureRoute::Bound => {}
+        }
+        self.gesture = Some(gesture);
+    }
+
+    /// Returns the gesture that triggered a bind, if any.
+    pub fn gesture_end(&mut self, cancelled: bool, time: u32) -> Option<Gesture> {
+        let mut gesture = self.gesture.take()?;
+
+        if gesture.route == GestureRoute::Bound {
+            if cancelled {
+                return None
+            }
+            return self.run_gesture_bind(&gesture)
+        }
+        if gesture.route == GestureRoute::Undecided {
+            // it never got far enough to fire a bind, so the client gets all of it
+            self.forward_held_back(&mut gesture);
+        }
+
+        let pointer = self.seat.get_pointer()?;
+        let serial = SERIAL_COUNTER.next_serial();
+        match gesture.kind {
+            GestureKind::Swipe => pointer.gesture_swipe_end(self, &GestureSwipeEndEvent { serial, time, cancelled }),
+            GestureKind::Pinch => pointer.gesture_pinch_end(self, &GesturePinchEndEvent { serial, time, cancelled }),
+            GestureKind::Hold => pointer.gesture_hold_end(self, &GestureHoldEndEvent { serial, time, cancelled })
+        }
+        None
+    }
+
+    /// Finished gesture given over the socket. It only runs the matching bind, clients never see it.
+    pub fn simulate_gesture(&mut self, kind: GestureKind, fingers: u32, delta: Point<f64, Logical>, scale: f64) -> Option<Gesture> {
+        self.run_gesture_bind(&ActiveGesture {
+            kind,
+            fingers,
+            delta,
+            scale,
+            route: GestureRoute::Bound,
+            begin_time: 0,
+            held_back: vec![]
+        })
+    }
+
+    /// Binds the gesture once it's recognized as one a bind uses, otherwise hands it over to the client.
+    fn decide_gesture(&mut self, gesture: &mut ActiveGesture) {
+        let Some(recognized) = gesture.recognize() else {
+            return
+        };
+        if self.config.gesture_binds.iter().any(|b| b.gesture == recognized) {
+            gesture.route = GestureRoute::Bound;
+            gesture.held_back.clear();
+        } else {
+            self.forward_held_back(gesture);
+        }
+    }
+
+    fn forward_held_back(&mut self, gesture: &mut ActiveGesture) {
+        gesture.route = GestureRoute::Forwarded;
+        self.send_gesture_begin(gesture.kind, gesture.fingers, gesture.begin_time);
+        for update in gesture.held_back.drain(..) {
+            self.send_gesture_update(gesture.kind, update.delta, update.scale, update.rotation, update.time);
+        }
+    }
+
+    fn send_gesture_begin(&mut self, kind: GestureKind, fingers: u32, time: u32) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return
+        };
+        let serial = SERIAL_COUNTER.next_serial();
+        match kind {
+            GestureKind::Swipe => pointer.gesture_swipe_begin(self, &GestureSwipeBeginEvent { serial, time, fingers }),
+            GestureKind::Pinch => pointer.gesture_pinch_begin(self, &GesturePinchBeginEvent { serial, time, fingers }),
+            GestureKind::Hold => pointer.gesture_hold_begin(self, &GestureHoldBeginEvent { serial, time, fingers })
+        }
+    }
+
+    fn send_gesture_update(&mut self, kind: GestureKind, delta: Point<f64, Logical>, scale: f64, rotation: f64, time: u32) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return
+        };
+        match kind {
+            GestureKind::Swipe => pointer.gesture_swipe_update(self, &SwipeUpdate { time, delta }),
+            GestureKind::Pinch => pointer.gesture_pinch_update(self, &PinchUpdate { time, delta, scale, rotation }),
+            GestureKind::Hold => {}
+        }
+    }
+
+    fn run_gesture_bind(&mut self, gesture: &ActiveGesture) -> Option<Gesture> {
+        let recognized = gesture.recognize()?;
+        let action = self.config.gesture_binds.iter().find(|b| b.gesture == recognized)?.action.clone();
+        run_keybind_action(self, action);
+        Some(recognized)
+    }
+}
+
+pub fn handle_gesture_begin<B: InputBackend>(state: &mut Waycrust, kind: GestureKind, event: impl GestureBeginEvent<B>) {
+    state.gesture_begin(kind, event.fingers(), event.time_msec());
+}
+
+pub fn handle_gesture_swipe_update<B: InputBackend>(state: &mut Waycrust, event: B::GestureSwipeUpdateEvent) {
+    state.gesture_update(event.delta(), 1.0, 0.0, event.time_msec());
+}
+
+pub fn handle_gesture_pinch_update<B: InputBackend>(state: &mut Waycrust, event: B::GesturePinchUpdateEvent) {
+    state.gesture_update(event.delta(), event.scale(), event.rotation(), event.time_msec());
+}
+
+pub fn handle_gesture_end<B: InputBackend>(state: &mut Waycrust, event: impl GestureEndEvent<B>) {
+    state.gesture_end(event.cancelled(), event.time_msec());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recognize(kind: GestureKind, delta: (f64, f64), scale: f64) -> Option<Gesture> {
+        ActiveGesture {
+            kind,
+            fingers: 3,
+            delta: delta.into(),
+            scale,
+            route: GestureRoute::Bound,
+            begin_time: 0,
+            held_back: vec![]
+        }.recognize()
+    }
+
+    #[test]
+    fn short_swipe_is_ignored() {
+        assert_eq!(recognize(GestureKind::Swipe, (30.0, -40.0), 1.0), None);
+    }
+
+    #[test]
+    fn swipe_takes_direction_of_longer_axis() {
+        let swipe = |direction| Some(Gesture::Swipe { fingers: 3, direction });
+        assert_eq!(recognize(GestureKind::Swipe, (120.0, 60.0), 1.0), swipe(SwipeDirection::Right));
+        assert_eq!(recognize(GestureKind::Swipe, (-120.0, 60.0), 1.0), swipe(SwipeDirection::Left));
+        assert_eq!(recognize(GestureKind::Swipe, (20.0, 80.0), 1.0), swipe(SwipeDirection::Down));
+        assert_eq!(recognize(GestureKind::Swipe, (20.0, -80.0), 1.0), swipe(SwipeDirection::Up));
+    }
+
+    #[test]
+    fn pinch_needs_enough_scale() {
+        let pinch = |direction| Some(Gesture::Pinch { fingers: 3, direction });
+        assert_eq!(recognize(GestureKind::Pinch, (0.0, 0.0), 0.5), pinch(PinchDirection::In));
+        assert_eq!(recognize(GestureKind::Pinch, (0.0, 0.0), 1.5), pinch(PinchDirection::Out));
+        assert_eq!(recognize(GestureKind::Pinch, (0.0, 0.0), 1.1), None);
+    }
+
+    #[test]
+    fn hold_always_counts() {
+        assert_eq!(recognize(GestureKind::Hold, (0.0, 0.0), 1.0), Some(Gesture::Hold { fingers: 3 }));
+    }
+}
@@ -1,43 +1,444 @@
-use smithay::input::keyboard::{FilterResult, KeyboardHandle};
+use std::time::{Duration, Instant};
+
+use smithay::{backend::input::Axis, wayland::keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitorSeat};
+use wayland_server::protocol::wl_surface::WlSurface;
+use xkbcommon::xkb::Keycode;
 
 use crate::{
-    config::KeybindAction::{self, *},
+    comp_utils::spawn_command,
+    config::{KeyboardConfig, Keybind, KeybindAction::{self, *}, KeybindShortcut, MouseBind, MouseTrigger},
+    handlers::input::modifiers::{KeybindXkb, PressedKey, active_mods},
+    rules::toplevel_identity,
     Waycrust
 };
 
-pub fn handle_keybind(state: &mut Waycrust, action: Option<KeybindAction>, keyboard: &KeyboardHandle<Waycrust>) -> FilterResult<()> {
-    if let Some(action) = action {
-        match action {
-            CycleNext => state.next_toplevel(),
-            CyclePrev => state.previous_toplevel(),
-            Kill => {
-                let focused = match keyboard.current_focus() {
-                    Some(f) => f,
-                    None => {
-                        return FilterResult::Intercept(())
-                    }
-                };
-                let toplevel = match state.xdg_shell_state.toplevel_surfaces().iter().find(|t| t.wl_surface() == &focused) {
-                    Some(t) => t,
-                    None => {
-                        return FilterResult::Intercept(())
-                    }
-                };
-                toplevel.send_close();
+// scroll distance of one wheel detent, smooth scrolling fires a scroll bind every this many units
+const SCROLL_STEP: f64 = 15.0;
+
+pub struct KeybindState {
+    pub mode: Option<String>,   // None is the default mode
+    pub last_activity: Instant,
+    pub chord: Option<PendingChord>,
+    pub last_pressed: Option<Keycode>,  // release keybinds fire only if no other key was pressed in between
+    pub repeating: Option<RepeatingKeybind>,
+    pub intercepted_keys: Vec<Keycode>,     // keys whose press was consumed by a keybind, same for their release
+    pub remapped_keys: Vec<(Keycode, Keycode)>,     // pressed keys swapped by a keysym remap, with the key they were swapped for
+    pub bound_buttons: Vec<u32>,    // buttons pressed for a mouse bind, their release isn't forwarded either
+    scroll: [f64; 2],       // scroll not yet turned into scroll binds, horizontal and vertical
+    pub xkb: Option<KeybindXkb>
+}
+
+impl KeybindState {
+    pub fn new(keyboard: &KeyboardConfig) -> Self {
+        Self {
+            mode: None,
+            last_activity: Instant::now(),
+            chord: None,
+            last_pressed: None,
+            repeating: None,
+            intercepted_keys: vec![],
+            remapped_keys: vec![],
+            bound_buttons: vec![],
+            scroll: [0.0; 2],
+            xkb: KeybindXkb::new(keyboard)
+        }
+    }
+
+    /// Matches a key press against `keybinds`, continuing the pending sequence unless it timed out.
+    pub fn match_keybind(&mut self, keybinds: &[Keybind], pressed: &PressedKey, inhibited: bool, timeout: Duration) -> KeybindMatch {
+        // modifiers pressed in the middle of a sequence are needed for its next key
+        if self.chord.is_some() && pressed.keysym.is_modifier_key() {
+            return KeybindMatch::None
+        }
+
+        let pending = self.chord.take().filter(|c| c.last_key.elapsed() < timeout);
+        let (depth, candidates) = match pending {
+            Some(chord) => (chord.depth, chord.candidates),
+            None => (0, (0..keybinds.len()).filter(|&i| !keybinds[i].on_release).collect())
+        };
+
+        let matched: Vec<usize> = candidates.into_iter()
+            .filter(|&i| keybinds[i].locked || !inhibited)
+            .filter(|&i| keybinds[i].shortcut.get(depth).is_some_and(|s| self.shortcut_matches(s, pressed)))
+            .collect();
+
+        if let Some(&i) = matched.iter().find(|&&i| keybinds[i].shortcut.len() == depth + 1) {
+            return KeybindMatch::Complete {
+                action: keybinds[i].action.clone(),
+                repeat: keybinds[i].repeat,
+                pass_through: keybinds[i].pass_through
             }
-            Exec(command) => {
-                let (command, args) = {
-                    let mut iter = shlex::Shlex::new(&command);
-                    let command = iter.next().unwrap_or_default().to_string();
-                    let args: Vec<String> = iter.collect();
-                    (command, args)
-                };
-
-                std::process::Command::new(command).args(args).spawn().ok();
+        }
+        if !matched.is_empty() {
+            self.chord = Some(PendingChord {
+                candidates: matched,
+                depth: depth + 1,
+                last_key: Instant::now()
+            });
+            return KeybindMatch::Prefix
+        }
+        if depth > 0 {
+            // unmatched key cancels the sequence and is treated as if it was pressed on its own
+            return self.match_keybind(keybinds, pressed, inhibited, timeout)
+        }
+        KeybindMatch::None
+    }
+
+    /// Release keybinds consist of a single shortcut, modifiers are matched as they are after the release.
+    pub fn match_release_keybind(&self, keybinds: &[Keybind], released: &PressedKey, inhibited: bool) -> Option<KeybindAction> {
+        keybinds.iter()
+            .filter(|kb| kb.on_release && kb.shortcut.len() == 1 && (kb.locked || !inhibited))
+            .find(|kb| self.shortcut_matches(&kb.shortcut[0], released))
+            .map(|kb| kb.action.clone())
+    }
+
+    fn shortcut_matches(&self, shortcut: &KeybindShortcut, pressed: &PressedKey) -> bool {
+        let Some(ref xkb) = self.xkb else {
+            return false
+        };
+        if shortcut.key.is_none() && shortcut.keycode.is_none() {
+            return false
+        }
+        if shortcut.key.is_some_and(|k| k != pressed.keysym) || shortcut.keycode.is_some_and(|c| c != pressed.keycode) {
+            return false
+        }
+
+        // keycode bindings don't depend on the keysym, so no modifier is consumed for them
+        let consumed = if shortcut.key.is_some() { pressed.consumed } else { 0 };
+        xkb.modifiers_match(&shortcut.modifiers, pressed.mods, consumed)
+    }
+}
+
+pub struct RepeatingKeybind {
+    pub keycode: Keycode,
+    action: KeybindAction,
+    next: Instant
+}
+
+/// Keybind sequence that was started but not finished yet.
+pub struct PendingChord {
+    candidates: Vec<usize>,     // indices into active keybinds matching keys pressed so far
+    depth: usize,
+    last_key: Instant
+}
+
+pub enum KeybindMatch {
+    Complete {
+        action: KeybindAction,
+        repeat: bool,
+        pass_through: bool
+    },
+    Prefix,
+    None
+}
+
+impl Waycrust {
+    pub fn active_keybinds(&self) -> &[Keybind] {
+        self.config.mode_keybinds(self.keybinds.mode.as_deref())
+    }
+
+    pub fn match_keybind(&mut self, pressed: &PressedKey) -> KeybindMatch {
+        let timeout = Duration::from_millis(self.config.chord_timeout);
+        let inhibited = self.shortcuts_inhibited();
+        let keybinds = self.config.mode_keybinds(self.keybinds.mode.as_deref());
+        self.keybinds.match_keybind(keybinds, pressed, inhibited, timeout)
+    }
+
+    pub fn match_release_keybind(&self, released: &PressedKey) -> Option<KeybindAction> {
+        self.keybinds.match_release_keybind(self.active_keybinds(), released, self.shortcuts_inhibited())
+    }
+
+    /// Reports custom modifiers of binds missing from the keymap once, when the keymap or config changes,
+    /// instead of on every key press.
+    pub fn check_keybind_modifiers(&self) {
+        let Some(ref xkb) = self.keybinds.xkb else {
+            return
+        };
+        let config = &self.config;
+        let keybinds = config.keybinds.iter().chain(config.modes.values().flat_map(|m| m.keybinds.iter()));
+        let mouse_binds = config.mouse_binds.iter().chain(config.modes.values().flat_map(|m| m.mouse_binds.iter()));
+        let modifiers = keybinds.flat_map(|kb| kb.shortcut.iter())
+            .chain(std::iter::once(&config.shortcuts_inhibit.escape))
+            .flat_map(|s| s.modifiers.iter())
+            .chain(mouse_binds.flat_map(|mb| mb.modifiers.iter()));
+        for name in xkb.unknown_modifiers(modifiers) {
+            println!("unknown modifier {}, binds using it are ignored", name);
+        }
+    }
+
+    /// Focused client asked for compositor shortcuts to be bypassed, only locked keybinds work then.
+    pub fn shortcuts_inhibited(&self) -> bool {
+        self.seat.keyboard_shortcuts_inhibited()
+    }
+
+    /// Only windows matching the allow list, if there is one, may inhibit keybinds.
+    pub fn shortcuts_inhibit_allowed(&self, surface: &WlSurface) -> bool {
+        let allow = &self.config.shortcuts_inhibit.allow;
+        allow.is_empty() || self.xdg_shell_state.toplevel_surfaces().iter()
+            .find(|t| t.wl_surface() == surface)
+            .is_some_and(|t| {
+                let (app_id, _) = toplevel_identity(t);
+                allow.iter().any(|m| m.matches(&app_id))
+            })
+    }
+
+    /// Toggles the inhibitor of the focused surface when the reserved escape shortcut is pressed.
+    pub fn handle_inhibit_escape(&mut self, pressed: &PressedKey) -> bool {
+        if !self.keybinds.shortcut_matches(&self.config.shortcuts_inhibit.escape, pressed) {
+            return false
+        }
+        let Some(surface) = self.seat.get_keyboard().and_then(|k| k.current_focus()) else {
+            return false
+        };
+        let Some(inhibitor) = self.seat.keyboard_shortcuts_inhibitor_for_surface(&surface) else {
+            return false
+        };
+
+        if inhibitor.is_active() {
+            inhibitor.inactivate();
+        } else if self.shortcuts_inhibit_allowed(&surface) {
+            inhibitor.activate();
+        }
+        true
+    }
+
+    /// Keybinds repeat with the same delay and rate as keys do in clients.
+    pub fn start_keybind_repeat(&mut self, keycode: Keycode, action: KeybindAction) {
+        if self.config.keyboard.repeat_rate <= 0 {
+            return
+        }
+        let delay = self.config.keyboard.repeat_delay.max(0) as u64;
+        self.keybinds.repeating = Some(RepeatingKeybind {
+            keycode,
+            action,
+            next: Instant::now() + Duration::from_millis(delay)
+        });
+    }
+
+    pub fn repeat_keybind(&mut self) {
+        let rate = self.config.keyboard.repeat_rate.max(1) as u64;
+        let Some(ref mut repeating) = self.keybinds.repeating else {
+            return
+        };
+        if repeating.next > Instant::now() {
+            return
+        }
+
+        repeating.next += Duration::from_millis(1000 / rate);
+        let action = repeating.action.clone();
+        run_keybind_action(self, action);
+    }
+
+    pub fn active_mouse_binds(&self) -> &[MouseBind] {
+        match self.keybinds.mode.as_ref().and_then(|m| self.config.modes.get(m)) {
+            Some(mode) => &mode.mouse_binds,
+            None => &self.config.mouse_binds
+        }
+    }
+
+    pub fn match_mouse_bind(&self, trigger: MouseTrigger) -> Option<KeybindAction> {
+        if !self.bindings_enabled() {
+            return None
+        }
+        let (Some(xkb), Some(keyboard)) = (&self.keybinds.xkb, self.seat.get_keyboard()) else {
+            return None
+        };
+        let active = active_mods(&keyboard.modifier_state());
+        self.active_mouse_binds().iter()
+            .find(|mb| mb.trigger == trigger && xkb.modifiers_match(&mb.modifiers, active, 0))
+            .map(|mb| mb.action.clone())
+    }
+
+    /// Runs scroll binds for the axis, returns false if there are none and the scroll belongs to the client.
+    pub fn handle_scroll_bind(&mut self, axis: Axis, amount: f64) -> bool {
+        let (idx, negative, positive) = match axis {
+            Axis::Horizontal => (0, MouseTrigger::ScrollLeft, MouseTrigger::ScrollRight),
+            Axis::Vertical => (1, MouseTrigger::ScrollUp, MouseTrigger::ScrollDown)
+        };
+        let negative = self.match_mouse_bind(negative);
+        let positive = self.match_mouse_bind(positive);
+        if negative.is_none() && positive.is_none() {
+            self.keybinds.scroll[idx] = 0.0;
+            return false
+        }
+        self.keybinds.last_pressed = None;
+
+        // changing direction starts counting again
+        let scroll = &mut self.keybinds.scroll[idx];
+        if *scroll * amount < 0.0 {
+            *scroll = 0.0;
+        }
+        *scroll += amount;
+
+        let steps = (*scroll / SCROLL_STEP).trunc();
+        *scroll -= steps * SCROLL_STEP;
+        let action = if steps < 0.0 { negative } else { positive };
+        if let Some(action) = action {
+            for _ in 0..steps.abs() as u32 {
+                run_keybind_action(self, action.clone());
             }
-        }   
-        FilterResult::Intercept(())
-    } else {
-        FilterResult::Forward
+        }
+        true
+    }
+
+    pub fn mode_name(&self) -> &str {
+        self.keybinds.mode.as_deref().unwrap_or("default")
+    }
+
+    pub fn enter_mode(&mut self, name: Option<String>) {
+        let name = name.filter(|n| n != "default");
+        if let Some(ref n) = name {
+            if !self.config.modes.contains_key(n) {
+                println!("unknown binding mode {}", n);
+                return
+            }
+        }
+
+        self.keybinds.last_activity = Instant::now();
+        self.keybinds.chord = None;
+        if self.keybinds.mode != name {
+            self.keybinds.mode = name;
+            self.ipc_events.push(format!("MODE {}", self.mode_name()));
+        }
+    }
+
+    pub fn check_mode_timeout(&mut self) {
+        let timeout = self.keybinds.mode.as_ref()
+            .and_then(|m| self.config.modes.get(m))
+            .and_then(|m| m.timeout);
+
+        if let Some(timeout) = timeout {
+            if self.keybinds.last_activity.elapsed() >= Duration::from_millis(timeout) {
+                self.enter_mode(None);
+            }
+        }
+    }
+}
+
+pub fn run_keybind_action(state: &mut Waycrust, action: KeybindAction) {
+    match action {
+        CycleNext => state.next_toplevel(),
+        CyclePrev => state.previous_toplevel(),
+        Kill => {
+            let focused = match state.seat.get_keyboard().and_then(|k| k.current_focus()) {
+                Some(f) => f,
+                None => return
+            };
+            if let Some(toplevel) = state.xdg_shell_state.toplevel_surfaces().iter().find(|t| t.wl_surface() == &focused) {
+                toplevel.send_close();
+            }
+        }
+        Exec(command) => {
+            spawn_command(&command);
+        }
+        MoveToScratchpad => state.move_to_scratchpad(),
+        ToggleScratchpad => state.toggle_scratchpad(),
+        Scratchpad(name) => state.toggle_named_scratchpad(&name),
+        EnterMode(name) => state.enter_mode(Some(name)),
+        ExitMode => state.enter_mode(None),
+        NextLayout => state.next_layout(),
+        PrevLayout => state.previous_layout(),
+        SetLayout(name) => state.select_layout(&name),
+        BreakPointerConstraint => state.break_pointer_constraint(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xkbcommon::xkb::Keysym;
+
+    use super::*;
+    use crate::handlers::input::modifiers;
+
+    const SHIFT: u32 = 1 << 0;
+    const LOGO: u32 = 1 << 6;
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn state() -> KeybindState {
+        let keyboard = ron::from_str(r#"(rules: "", model: "", layout: "us", variant: "", options: None)"#).unwrap();
+        let mut state = KeybindState::new(&keyboard);
+        state.xkb = Some(modifiers::tests::xkb());
+        state
+    }
+
+    fn keybinds(keybinds: &str) -> Vec<Keybind> {
+        ron::from_str(keybinds).unwrap()
+    }
+
+    fn key(keysym: Keysym, code: u32, mods: u32) -> PressedKey {
+        PressedKey { keycode: Keycode::new(code + 8), keysym, mods, consumed: 0 }
+    }
+
+    fn exec(matched: KeybindMatch) -> Option<String> {
+        match matched {
+            KeybindMatch::Complete { action: Exec(command), .. } => Some(command),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn sequence_completes_on_last_key() {
+        let binds = keybinds(r#"[(shortcut: [(key: "x", modifiers: [Logo]), (key: "t")], action: Exec("term"))]"#);
+        let mut state = state();
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::x, 45, LOGO), false, TIMEOUT), KeybindMatch::Prefix));
+        // modifiers released or pressed in between don't cancel it
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::Super_L, 125, LOGO), false, TIMEOUT), KeybindMatch::None));
+        assert_eq!(exec(state.match_keybind(&binds, &key(Keysym::t, 20, 0), false, TIMEOUT)), Some("term".to_string()));
+        assert!(state.chord.is_none());
+    }
+
+    #[test]
+    fn unmatched_key_cancels_sequence_and_matches_alone() {
+        let binds = keybinds(r#"[
+            (shortcut: [(key: "x", modifiers: [Logo]), (key: "t")], action: Exec("term")),
+            (shortcut: (key: "q"), action: Exec("quit"))
+        ]"#);
+        let mut state = state();
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::x, 45, LOGO), false, TIMEOUT), KeybindMatch::Prefix));
+        assert_eq!(exec(state.match_keybind(&binds, &key(Keysym::q, 16, 0), false, TIMEOUT)), Some("quit".to_string()));
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::t, 20, 0), false, TIMEOUT), KeybindMatch::None));
+    }
+
+    #[test]
+    fn sequence_times_out() {
+        let binds = keybinds(r#"[(shortcut: [(key: "x", modifiers: [Logo]), (key: "t")], action: Exec("term"))]"#);
+        let mut state = state();
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::x, 45, LOGO), false, Duration::ZERO), KeybindMatch::Prefix));
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::t, 20, 0), false, Duration::ZERO), KeybindMatch::None));
+    }
+
+    #[test]
+    fn release_keybinds_only_match_on_release() {
+        let binds = keybinds(r#"[(shortcut: (key: "Super_L"), action: Exec("launcher"), on_release: true)]"#);
+        let mut state = state();
+        let logo = key(Keysym::Super_L, 125, 0);
+        assert!(matches!(state.match_keybind(&binds, &logo, false, TIMEOUT), KeybindMatch::None));
+        assert!(matches!(state.match_release_keybind(&binds, &logo, false), Some(Exec(c)) if c == "launcher"));
+    }
+
+    #[test]
+    fn only_locked_keybinds_work_while_inhibited() {
+        let binds = keybinds(r#"[
+            (shortcut: (key: "a", modifiers: [Logo]), action: Exec("normal")),
+            (shortcut: (key: "b", modifiers: [Logo]), action: Exec("locked"), locked: true)
+        ]"#);
+        let mut state = state();
+        assert!(matches!(state.match_keybind(&binds, &key(Keysym::a, 30, LOGO), true, TIMEOUT), KeybindMatch::None));
+        assert_eq!(exec(state.match_keybind(&binds, &key(Keysym::b, 48, LOGO), true, TIMEOUT)), Some("locked".to_string()));
+    }
+
+    #[test]
+    fn keycode_keybinds_count_consumed_modifiers() {
+        let binds = keybinds(r#"[
+            (shortcut: (key: "exclam", modifiers: [Logo]), action: Exec("keysym")),
+            (shortcut: (keycode: "KEY_2", modifiers: [Logo]), action: Exec("keycode"))
+        ]"#);
+        let mut state = state();
+        let exclam = PressedKey { consumed: SHIFT, ..key(Keysym::exclam, 2, LOGO | SHIFT) };
+        assert_eq!(exec(state.match_keybind(&binds, &exclam, false, TIMEOUT)), Some("keysym".to_string()));
+
+        let at = PressedKey { consumed: SHIFT, ..key(Keysym::at, 3, LOGO | SHIFT) };
+        assert!(matches!(state.match_keybind(&binds, &at, false, TIMEOUT), KeybindMatch::None));
+        assert_eq!(exec(state.match_keybind(&binds, &key(Keysym::from_char('2'), 3, LOGO), false, TIMEOUT)), Some("keycode".to_string()));
     }
-}
\ No newline at end of file
+}
@@ -1,54 +1,118 @@
-use smithay::{backend::{
-    input::KeyboardKeyEvent,
-    winit::WinitKeyboardInputEvent
-}, input::keyboard::FilterResult, utils::SERIAL_COUNTER};
+use smithay::{backend::input::{Event, InputBackend, KeyState, KeyboardKeyEvent}, input::keyboard::FilterResult, utils::SERIAL_COUNTER};
+use xkbcommon::xkb::Keycode;
 
 use crate::{
     compositor::Waycrust, 
-    config::KeybindShortcut,
-    handlers::input::keybinds::handle_keybind
+    handlers::input::keybinds::{KeybindMatch, run_keybind_action}
 };
 
 
 
-pub fn handle_keyboard_event(state: &mut Waycrust, event: WinitKeyboardInputEvent) {
+pub fn handle_keyboard_event<B: InputBackend>(state: &mut Waycrust, event: B::KeyboardKeyEvent) {
     let keyboard = match state.seat.get_keyboard() {    // might replace it with loop if I decide to add multiple seats to distinguish inputs of the same type
         Some(k) => k,
         None => return
     };
-    let key_state = event.state();
-    let mut key_code = event.key_code();
-    for remap in &state.config.remaps {
-        if remap.from.raw() == key_code.raw() {
-            key_code = remap.into.raw().into();
-            break
-        }
+    // virtual keyboards come with a keymap of their own
+    if !state.virtual_input {
+        state.switch_keyboard_device(&event.device());
     }
+    let bindings = state.bindings_enabled();
+    let key_state = event.state();
+    let key_code = if bindings {
+        state.remap_key(event.key_code(), key_state)
+    } else {
+        event.key_code()
+    };
     keyboard.input::<(), _>(
         state,
         key_code,
         key_state,
         SERIAL_COUNTER.next_serial(),
-        0,
-        |state, modifiers, handle| {
-            let key_symbol = handle.modified_sym();
-            if key_state == smithay::backend::input::KeyState::Pressed {
-                let pressed = KeybindShortcut::new_verbose(
-                    key_symbol,
-                    modifiers.alt,
-                    modifiers.ctrl,
-                    modifiers.shift,
-                    modifiers.logo
-                );
-                let action = state.config.keybinds
-                    .iter()
-                    .find(|kb| kb.shortcut == pressed)
-                    .map(|kb| kb.action.clone());
-
-                handle_keybind(state, action, &keyboard)
+        event.time_msec(),
+        |state, modifiers, _handle| {
+            let Some(ref mut xkb) = state.keybinds.xkb else {
+                return FilterResult::Forward
+            };
+            let pressed = xkb.translate(key_code, modifiers);
+            state.layout_changed(modifiers.serialized.layout_effective);
+            if key_state == KeyState::Released {
+                if state.keybinds.repeating.as_ref().is_some_and(|r| r.keycode == key_code) {
+                    state.keybinds.repeating = None;
+                }
+                // the client never saw the press, so it mustn't see the release either
+                if let Some(idx) = state.keybinds.intercepted_keys.iter().position(|&k| k == key_code) {
+                    state.keybinds.intercepted_keys.remove(idx);
+                    state.keybinds.last_pressed = None;
+                    return FilterResult::Intercept(())
+                }
+            }
+            if !bindings {
+                return FilterResult::Forward
+            }
+            if key_state == KeyState::Pressed {
+                state.keybinds.last_activity = std::time::Instant::now();
+                state.keybinds.last_pressed = Some(key_code);
+                // checked before keybinds, so it can't be inhibited itself
+                let forward = if state.handle_inhibit_escape(&pressed) {
+                    false
+                } else {
+                    match state.match_keybind(&pressed) {
+                        KeybindMatch::Complete { action, repeat, pass_through } => {
+                            if repeat {
+                                state.start_keybind_repeat(key_code, action.clone());
+                            }
+                            run_keybind_action(state, action);
+                            pass_through
+                        }
+                        // keys consumed by an unfinished sequence never reach the client
+                        KeybindMatch::Prefix => false,
+                        KeybindMatch::None => true
+                    }
+                };
+                if forward {
+                    FilterResult::Forward
+                } else {
+                    state.keybinds.intercepted_keys.push(key_code);
+                    FilterResult::Intercept(())
+                }
             } else {
+                if state.keybinds.last_pressed.take() == Some(key_code) {
+                    if let Some(action) = state.match_release_keybind(&pressed) {
+                        run_keybind_action(state, action);
+                    }
+                }
                 FilterResult::Forward
             }
         },
     );
-}
\ No newline at end of file
+}
+
+impl Waycrust {
+    /// Applies remaps before the key reaches xkb, so keybinds and clients see the same key. Keysym remaps
+    /// swap in a key producing the target keysym, its release goes to the same key.
+    fn remap_key(&mut self, keycode: Keycode, key_state: KeyState) -> Keycode {
+        if key_state == KeyState::Released {
+            if let Some(idx) = self.keybinds.remapped_keys.iter().position(|(physical, _)| *physical == keycode) {
+                return self.keybinds.remapped_keys.remove(idx).1
+            }
+            return self.config.remap_keycode(keycode)
+        }
+
+        let key = self.config.remap_keycode(keycode);
+        let (Some(keyboard), Some(xkb)) = (self.seat.get_keyboard(), self.keybinds.xkb.as_mut()) else {
+            return key
+        };
+        let modifiers = keyboard.modifier_state();
+        let Some(into) = self.config.remap_keysym(xkb.keysym(key, &modifiers)) else {
+            return key
+        };
+        match xkb.keycode_for(into, &modifiers) {
+            Some(remapped) => {
+                self.keybinds.remapped_keys.push((keycode, remapped));
+                remapped
+            }
+            None => key
+        }
+    }
+}
@@ -1,3 +1,61 @@
 pub mod keyboard;
 pub mod keybinds;
-pub mod pointer;
\ No newline at end of file
+pub mod modifiers;
+pub mod pointer;
+pub mod touch;
+pub mod gestures;
+pub mod tablet;
+
+use smithay::{backend::input::{InputBackend, InputEvent}, utils::{Logical, Rectangle}};
+
+use crate::{
+    compositor::Waycrust,
+    handlers::input::{
+        gestures::{GestureKind, handle_gesture_begin, handle_gesture_end, handle_gesture_pinch_update, handle_gesture_swipe_update},
+        keyboard::handle_keyboard_event,
+        tablet::{handle_device_added, handle_device_removed, handle_tablet_tool_axis, handle_tablet_tool_button, handle_tablet_tool_proximity, handle_tablet_tool_tip},
+        pointer::{handle_pointer_axis, handle_pointer_button, handle_pointer_movement, handle_pointer_relative_movement},
+        touch::{handle_touch_cancel, handle_touch_down, handle_touch_frame, handle_touch_motion, handle_touch_up}
+    }
+};
+
+/// Routes input from any backend to its handler.
+pub fn handle_input_event<B: InputBackend>(state: &mut Waycrust, event: InputEvent<B>) {
+    match event {
+        InputEvent::Keyboard { event } => handle_keyboard_event::<B>(state, event),
+        InputEvent::PointerMotion { event } => handle_pointer_relative_movement::<B>(state, event),
+        InputEvent::PointerMotionAbsolute { event } => handle_pointer_movement::<B>(state, event),
+        InputEvent::PointerButton { event } => handle_pointer_button::<B>(state, event),
+        InputEvent::PointerAxis { event } => handle_pointer_axis::<B>(state, event),
+        InputEvent::TouchDown { event } => handle_touch_down::<B>(state, event),
+        InputEvent::TouchMotion { event } => handle_touch_motion::<B>(state, event),
+        InputEvent::TouchUp { event } => handle_touch_up::<B>(state, event),
+        InputEvent::TouchCancel { event } => handle_touch_cancel::<B>(state, event),
+        InputEvent::TouchFrame { .. } => handle_touch_frame(state),
+        InputEvent::GestureSwipeBegin { event } => handle_gesture_begin::<B>(state, GestureKind::Swipe, event),
+        InputEvent::GestureSwipeUpdate { event } => handle_gesture_swipe_update::<B>(state, event),
+        InputEvent::GestureSwipeEnd { event } => handle_gesture_end::<B>(state, event),
+        InputEvent::GesturePinchBegin { event } => handle_gesture_begin::<B>(state, GestureKind::Pinch, event),
+        InputEvent::GesturePinchUpdate { event } => handle_gesture_pinch_update::<B>(state, event),
+        InputEvent::GesturePinchEnd { event } => handle_gesture_end::<B>(state, event),
+        InputEvent::GestureHoldBegin { event } => handle_gesture_begin::<B>(state, GestureKind::Hold, event),
+        InputEvent::GestureHoldEnd { event } => handle_gesture_end::<B>(state, event),
+        InputEvent::DeviceAdded { device } => handle_device_added::<B>(state, device),
+        InputEvent::DeviceRemoved { device } => handle_device_removed::<B>(state, device),
+        InputEvent::TabletToolProximity { event } => handle_tablet_tool_proximity::<B>(state, event),
+        InputEvent::TabletToolAxis { event } => handle_tablet_tool_axis::<B>(state, event),
+        InputEvent::TabletToolTip { event } => handle_tablet_tool_tip::<B>(state, event),
+        InputEvent::TabletToolButton { event } => handle_tablet_tool_button::<B>(state, event),
+        _ => {}
+    }
+}
+
+impl Waycrust {
+    /// Output area an absolute device maps onto, the whole output unless a region is configured.
+    pub fn input_region(&self, region: Option<(i32, i32, i32, i32)>) -> Option<Rectangle<i32, Logical>> {
+        let size = self.size?;
+        Some(region
+            .map(|(x, y, w, h)| Rectangle::new((x, y).into(), (w, h).into()))
+            .unwrap_or_else(|| Rectangle::from_size(size)))
+    }
+}
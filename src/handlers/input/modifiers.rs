@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use smithay::input::keyboard::ModifiersState;
+use xkbcommon::xkb::{self, Keycode, Keysym};
+
+use crate::config::{KeyboardConfig, KeyboardModifier};
+
+// real modifiers always take the first eight indices of a keymap
+const MOD_SHIFT: u32 = 1 << 0;
+const MOD_LOCK: u32 = 1 << 1;
+const MOD_CONTROL: u32 = 1 << 2;
+const MOD_1: u32 = 1 << 3;
+const MOD_2: u32 = 1 << 4;
+const MOD_3: u32 = 1 << 5;
+const MOD_4: u32 = 1 << 6;
+const MOD_5: u32 = 1 << 7;
+const REAL_MODS: u32 = 0xff;
+
+// Caps Lock and Num Lock are ignored unless a binding lists them
+const LOCK_MODS: u32 = MOD_LOCK | MOD_2;
+
+/// Key press as seen by keybinds.
+pub struct PressedKey {
+    pub keycode: Keycode,
+    pub keysym: Keysym,     // translated without Caps Lock
+    pub mods: u32,          // active real modifiers
+    pub consumed: u32       // modifiers used up to produce the keysym
+}
+
+/// Second xkb state following the keyboard one, used to translate keys for keybinds.
+pub struct KeybindXkb {
+    state: xkb::State,
+    mods: HashMap<String, u32>     // every modifier of the keymap by name, as a mask of real modifiers
+}
+
+impl KeybindXkb {
+    pub fn new(config: &KeyboardConfig) -> Option<Self> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let Some(keymap) = xkb::Keymap::new_from_names(
+            &context,
+            &config.rules,
+            &config.model,
+            &config.layout,
+            &config.variant,
+            config.options.clone(),
+            xkb::KEYMAP_COMPILE_NO_FLAGS
+        ) else {
+            println!("cannot compile keymap for keybinds");
+            return None
+        };
+
+        Some(Self::from_keymap(&keymap))
+    }
+
+    fn from_keymap(keymap: &xkb::Keymap) -> Self {
+        let mods = (0..keymap.num_mods())
+            .map(|idx| (keymap.mod_get_name(idx).to_string(), virtual_mod_mapping(keymap, idx)))
+            .collect();
+
+        Self {
+            state: xkb::State::new(keymap),
+            mods
+        }
+    }
+
+    pub fn translate(&mut self, keycode: Keycode, modifiers: &ModifiersState) -> PressedKey {
+        self.update_state(modifiers);
+
+        let consumed = (0..8)
+            .filter(|&idx| self.state.mod_index_is_consumed(keycode, idx))
+            .fold(0, |acc, idx| acc | 1 << idx);
+
+        PressedKey {
+            keycode,
+            keysym: self.state.key_get_one_sym(keycode),
+            mods: active_mods(modifiers),
+            consumed
+        }
+    }
+
+    /// Keysym the key produces for keybinds with the given modifiers.
+    pub fn keysym(&mut self, keycode: Keycode, modifiers: &ModifiersState) -> Keysym {
+        self.update_state(modifiers);
+        self.state.key_get_one_sym(keycode)
+    }
+
+    /// First key producing `keysym` with the given modifiers.
+    pub fn keycode_for(&mut self, keysym: Keysym, modifiers: &ModifiersState) -> Option<Keycode> {
+        self.update_state(modifiers);
+        let keymap = self.state.get_keymap();
+        (keymap.min_keycode().raw()..=keymap.max_keycode().raw())
+            .map(Keycode::new)
+            .find(|&keycode| self.state.key_get_one_sym(keycode) == keysym)
+    }
+
+    fn update_state(&mut self, modifiers: &ModifiersState) {
+        let serialized = modifiers.serialized;
+        self.state.update_mask(
+            serialized.depressed & !MOD_LOCK,
+            serialized.latched & !MOD_LOCK,
+            serialized.locked & !MOD_LOCK,
+            0,
+            0,
+            serialized.layout_effective
+        );
+    }
+
+    /// Real modifiers the binding modifiers stand for, None if one of them isn't in the keymap.
+    pub fn mod_mask(&self, modifiers: &[KeyboardModifier]) -> Option<u32> {
+        modifiers.iter().try_fold(0, |acc, m| {
+            let mask = match m {
+                KeyboardModifier::Shift => MOD_SHIFT,
+                KeyboardModifier::CapsLock => MOD_LOCK,
+                KeyboardModifier::Ctrl => MOD_CONTROL,
+                KeyboardModifier::Alt => MOD_1,
+                KeyboardModifier::NumLock => MOD_2,
+                KeyboardModifier::Logo => MOD_4,
+                KeyboardModifier::Mod1 => MOD_1,
+                KeyboardModifier::Mod2 => MOD_2,
+                KeyboardModifier::Mod3 => MOD_3,
+                KeyboardModifier::Mod4 => MOD_4,
+                KeyboardModifier::Mod5 => MOD_5,
+                KeyboardModifier::AltGr => self.named_mod("LevelThree").unwrap_or(MOD_5),
+                KeyboardModifier::Hyper => self.named_mod("Hyper").unwrap_or(MOD_3),
+                KeyboardModifier::Custom(name) => self.named_mod(name)?
+            };
+            Some(acc | mask)
+        })
+    }
+
+    /// Whether exactly the binding modifiers are held. Lock modifiers and modifiers consumed by the
+    /// key only count when the binding lists them.
+    pub fn modifiers_match(&self, modifiers: &[KeyboardModifier], active: u32, consumed: u32) -> bool {
+        let Some(wanted) = self.mod_mask(modifiers) else {
+            return false
+        };
+        let ignored = (LOCK_MODS | consumed) & !wanted;
+        active & !ignored == wanted
+    }
+
+    /// Names of custom modifiers the keymap doesn't have, binds using them never match.
+    pub fn unknown_modifiers<'a>(&self, modifiers: impl IntoIterator<Item = &'a KeyboardModifier>) -> Vec<&'a str> {
+        let mut unknown: Vec<&str> = modifiers.into_iter()
+            .filter_map(|m| match m {
+                KeyboardModifier::Custom(name) if self.named_mod(name).is_none() => Some(name.as_str()),
+                _ => None
+            })
+            .collect();
+        unknown.sort();
+        unknown.dedup();
+        unknown
+    }
+
+    fn named_mod(&self, name: &str) -> Option<u32> {
+        self.mods.get(name).copied().filter(|&mask| mask != 0)
+    }
+}
+
+pub fn active_mods(modifiers: &ModifiersState) -> u32 {
+    let serialized = modifiers.serialized;
+    (serialized.depressed | serialized.latched | serialized.locked) & REAL_MODS
+}
+
+/// Real modifiers a modifier is mapped to, found by checking which of them activate it.
+fn virtual_mod_mapping(keymap: &xkb::Keymap, idx: xkb::ModIndex) -> u32 {
+    if idx < 8 {
+        return 1 << idx
+    }
+    let mut state = xkb::State::new(keymap);
+    (0..8).filter(|real| {
+        state.update_mask(1 << real, 0, 0, 0, 0, 0);
+        state.mod_index_is_active(idx, xkb::STATE_MODS_EFFECTIVE)
+    }).fold(0, |acc, real| acc | 1 << real)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // small keymap of its own, so tests don't depend on the xkb data installed
+    const KEYMAP: &str = r#"xkb_keymap {
+        xkb_keycodes {
+            minimum = 8;
+            maximum = 255;
+            <AE01> = 10; <AE02> = 11; <AD01> = 24; <AD05> = 28; <AC01> = 38; <AB02> = 53; <AB05> = 56;
+            <LFSH> = 50; <CAPS> = 66; <LCTL> = 37; <LALT> = 64; <NMLK> = 77; <RALT> = 108;
+            <LWIN> = 133; <RWIN> = 134; <MENU> = 135;
+        };
+        xkb_types {
+            type "ONE_LEVEL" { modifiers = none; level_name[Level1] = "Any"; };
+            type "TWO_LEVEL" { modifiers = Shift; map[Shift] = Level2; level_name[Level1] = "Base"; level_name[Level2] = "Shift"; };
+            type "ALPHABETIC" { modifiers = Shift+Lock; map[Shift] = Level2; map[Lock] = Level2; level_name[Level1] = "Base"; level_name[Level2] = "Caps"; };
+        };
+        xkb_compatibility {
+            virtual_modifiers LevelThree = Mod5;
+            interpret ISO_Level3_Shift { virtualModifier = LevelThree; action = SetMods(modifiers = LevelThree); };
+        };
+        xkb_symbols {
+            key <AE01> { [ 1, exclam ] };
+            key <AE02> { [ 2, at ] };
+            key <AD01> { [ q, Q ] };
+            key <AD05> { [ t, T ] };
+            key <AC01> { [ a, A ] };
+            key <AB02> { [ x, X ] };
+            key <AB05> { [ b, B ] };
+            key <LFSH> { [ Shift_L ] };
+            key <CAPS> { [ Caps_Lock ] };
+            key <LCTL> { [ Control_L ] };
+            key <LALT> { [ Alt_L ] };
+            key <NMLK> { [ Num_Lock ] };
+            key <RALT> { [ ISO_Level3_Shift ] };
+            key <LWIN> { [ Super_L ] };
+            key <RWIN> { [ Super_R ] };
+            key <MENU> { [ Menu ] };
+            modifier_map Shift { <LFSH> };
+            modifier_map Lock { <CAPS> };
+            modifier_map Control { <LCTL> };
+            modifier_map Mod1 { <LALT> };
+            modifier_map Mod2 { <NMLK> };
+            modifier_map Mod4 { <LWIN>, <RWIN> };
+            modifier_map Mod5 { <RALT> };
+        };
+    };"#;
+
+    pub fn xkb() -> KeybindXkb {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_string(&context, KEYMAP.to_string(), xkb::KEYMAP_FORMAT_TEXT_V1, xkb::KEYMAP_COMPILE_NO_FLAGS).unwrap();
+        KeybindXkb::from_keymap(&keymap)
+    }
+
+    #[test]
+    fn lock_modifiers_are_ignored_unless_listed() {
+        let xkb = xkb();
+        assert!(xkb.modifiers_match(&[KeyboardModifier::Logo], MOD_4 | MOD_LOCK | MOD_2, 0));
+        assert!(!xkb.modifiers_match(&[KeyboardModifier::Logo, KeyboardModifier::CapsLock], MOD_4, 0));
+        assert!(xkb.modifiers_match(&[KeyboardModifier::Logo, KeyboardModifier::CapsLock], MOD_4 | MOD_LOCK, 0));
+    }
+
+    #[test]
+    fn extra_modifiers_fail_the_match() {
+        let xkb = xkb();
+        assert!(!xkb.modifiers_match(&[KeyboardModifier::Logo], MOD_4 | MOD_SHIFT, 0));
+        assert!(!xkb.modifiers_match(&[], MOD_CONTROL, 0));
+    }
+
+    #[test]
+    fn consumed_modifiers_are_ignored_unless_listed() {
+        let xkb = xkb();
+        assert!(xkb.modifiers_match(&[KeyboardModifier::Logo], MOD_4 | MOD_SHIFT, MOD_SHIFT));
+        assert!(xkb.modifiers_match(&[KeyboardModifier::Logo, KeyboardModifier::Shift], MOD_4 | MOD_SHIFT, MOD_SHIFT));
+    }
+
+    #[test]
+    fn named_modifiers_resolve_through_the_keymap() {
+        let xkb = xkb();
+        assert_eq!(xkb.mod_mask(&[KeyboardModifier::AltGr]), Some(MOD_5));
+        assert_eq!(xkb.mod_mask(&[KeyboardModifier::Custom("Mod4".to_string())]), Some(MOD_4));
+        assert_eq!(xkb.mod_mask(&[KeyboardModifier::Custom("NoSuchModifier".to_string())]), None);
+    }
+
+    #[test]
+    fn unknown_modifiers_are_reported_once() {
+        let xkb = xkb();
+        let modifiers = [
+            KeyboardModifier::Custom("NoSuchModifier".to_string()),
+            KeyboardModifier::Custom("Mod4".to_string()),
+            KeyboardModifier::Logo,
+            KeyboardModifier::Custom("NoSuchModifier".to_string())
+        ];
+        assert_eq!(xkb.unknown_modifiers(&modifiers), vec!["NoSuchModifier"]);
+    }
+
+    #[test]
+    fn keycode_for_finds_key_of_keysym() {
+        let mut xkb = xkb();
+        let mods = ModifiersState::default();
+        assert_eq!(xkb.keysym(Keycode::new(127 + 8), &mods), Keysym::Menu);
+        assert_eq!(xkb.keycode_for(Keysym::Super_R, &mods), Some(Keycode::new(126 + 8)));
+        assert_eq!(xkb.keycode_for(Keysym::Cyrillic_a, &mods), None);
+    }
+}
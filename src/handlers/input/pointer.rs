@@ -1,46 +1,258 @@
-use smithay::{backend::{input::{AbsolutePositionEvent, PointerButtonEvent}, winit::{WinitMouseInputEvent, WinitMouseMovedEvent}}, input::pointer::{ButtonEvent, MotionEvent}, utils::SERIAL_COUNTER};
+use smithay::{backend::input::{AbsolutePositionEvent, Axis, AxisRelativeDirection, AxisSource, ButtonState, Event, InputBackend, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent}, input::pointer::{AxisFrame, ButtonEvent, MotionEvent, RelativeMotionEvent}, utils::{Logical, Point, SERIAL_COUNTER}, wayland::pointer_constraints::{PointerConstraint, with_pointer_constraint}};
+use wayland_server::protocol::wl_surface::WlSurface;
 
-use crate::compositor::Waycrust;
+use crate::{compositor::Waycrust, config::{FocusPolicy, MouseTrigger}, handlers::input::keybinds::run_keybind_action};
 
-pub fn handle_pointer_movement(state: &mut Waycrust, event: WinitMouseMovedEvent) {
+pub fn handle_pointer_movement<B: InputBackend>(state: &mut Waycrust, event: B::PointerMotionAbsoluteEvent) {
+    let Some(size) = state.size else {
+        return
+    };
+    let location = event.position_transformed(size);
+    pointer_motion(state, location, event.time_msec());
+}
+
+/// Motion of devices like mice and touchpads, the pointer is kept inside the output.
+pub fn handle_pointer_relative_movement<B: InputBackend>(state: &mut Waycrust, event: B::PointerMotionEvent) {
+    let (Some(pointer), Some(size)) = (state.seat.get_pointer(), state.size) else {
+        return
+    };
+    // raw motion is delivered even while the pointer is locked, always to the surface holding pointer focus
+    let focus = pointer.current_focus().map(|surface| {
+        let origin = state.surface_under(pointer.current_location())
+            .filter(|(_, under, _)| *under == surface)
+            .map(|(_, _, origin)| origin)
+            .unwrap_or_default();
+        (surface, origin)
+    });
+    pointer.relative_motion(state, focus, &RelativeMotionEvent {
+        delta: event.delta(),
+        delta_unaccel: event.delta_unaccel(),
+        utime: event.time()
+    });
+
+    let mut location = pointer.current_location() + event.delta();
+    location.x = location.x.clamp(0.0, (size.w - 1) as f64);
+    location.y = location.y.clamp(0.0, (size.h - 1) as f64);
+    pointer_motion(state, location, event.time_msec());
+}
+
+fn pointer_motion(state: &mut Waycrust, location: Point<f64, Logical>, time: u32) {
     let pointer = match state.seat.get_pointer() {
         Some(p) => p,
         None => return
     };
-    let point = event.position();
 
-    let location = (point.x, point.y).into();
+    let Some(location) = state.constrain_pointer_motion(location) else {
+        pointer.frame(state);
+        return
+    };
 
     let event = MotionEvent {
         location,
         serial: SERIAL_COUNTER.next_serial(),
-        time: 0
+        time
     };
 
-    let focus = match state.toplevels.focused {
-        Some(ref f) => Some((f.wl_surface().clone(), (0.0, 0.0).into())),
-        None => None
+    let under = state.surface_under(location);
+
+    let window = under.as_ref().map(|(top, _, _)| top.clone());
+    let focus_window = match state.config.focus.policy {
+        FocusPolicy::ClickToFocus => false,
+        FocusPolicy::FollowsMouse => true,
+        FocusPolicy::Sloppy => window.is_some()
     };
+    if focus_window && !pointer.is_grabbed() && state.toplevels.focused != window {
+        state.focus_toplevel(window);
+    }
+
+    let focus = under.map(|(_, surface, loc)| (surface, loc));
 
     pointer.motion(
         state,
-        focus,
+        focus.clone(),
         &event,
     );
     pointer.frame(state);
+
+    if let Some((surface, origin)) = focus {
+        state.activate_pointer_constraint(&surface, location - origin);
+    }
 }
 
-pub fn handle_pointer_button(state: &mut Waycrust, event: WinitMouseInputEvent) {
+impl Waycrust {
+    /// Where the active constraint of the focused surface lets the pointer move on its way to `location`,
+    /// None if it's locked.
+    fn constrain_pointer_motion(&self, location: Point<f64, Logical>) -> Option<Point<f64, Logical>> {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return Some(location)
+        };
+        let Some((_, surface, origin)) = self.surface_under(pointer.current_location()) else {
+            return Some(location)
+        };
+
+        let current = pointer.current_location();
+        with_pointer_constraint(&surface, &pointer, |constraint| match constraint {
+            // a constraint only applies while the pointer is inside its region
+            Some(c) if c.is_active() && c.region().is_none_or(|r| r.contains((current - origin).to_i32_round())) => match &*c {
+                PointerConstraint::Locked(_) => None,
+                PointerConstraint::Confined(confined) => {
+                    let inside = |point: Point<f64, Logical>| {
+                        self.surface_under(point).is_some_and(|(_, s, _)| s == surface)
+                            && confined.region().is_none_or(|r| r.contains((point - origin).to_i32_round()))
+                    };
+                    // each axis moves as far as the region allows, so the pointer reaches its edge and slides along it
+                    let moved_x = clamp_motion(current, (location.x - current.x, 0.0).into(), &inside);
+                    Some(clamp_motion(moved_x, (0.0, location.y - current.y).into(), &inside))
+                }
+            },
+            _ => Some(location)
+        })
+    }
+
+    /// Activates the constraint of the surface once the pointer is inside its region.
+    pub fn activate_pointer_constraint(&mut self, surface: &WlSurface, position: Point<f64, Logical>) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return
+        };
+        // a broken constraint stays off until the pointer leaves its surface
+        if self.broken_constraint.as_ref() == Some(surface) {
+            return
+        }
+        self.broken_constraint = None;
+
+        with_pointer_constraint(surface, &pointer, |constraint| {
+            if let Some(c) = constraint {
+                if !c.is_active() && c.region().is_none_or(|r| r.contains(position.to_i32_round())) {
+                    c.activate();
+                }
+            }
+        });
+    }
+
+    pub fn break_pointer_constraint(&mut self) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return
+        };
+        let Some(surface) = pointer.current_focus() else {
+            return
+        };
+        with_pointer_constraint(&surface, &pointer, |constraint| {
+            if let Some(c) = constraint {
+                c.deactivate();
+            }
+        });
+        self.broken_constraint = Some(surface);
+    }
+}
+
+pub fn handle_pointer_button<B: InputBackend>(state: &mut Waycrust, event: B::PointerButtonEvent) {
     let pointer = match state.seat.get_pointer() {
         Some(p) => p,
         None => return
     };
+
+    let button = event.button_code();
+    if event.state() == ButtonState::Pressed {
+        // a click in between cancels release keybinds, like any other key press
+        state.keybinds.last_pressed = None;
+        if let Some(action) = state.match_mouse_bind(MouseTrigger::Button(button)) {
+            // actions like Kill apply to the clicked window
+            let window = state.surface_under(pointer.current_location()).map(|(w, _, _)| w);
+            if window.is_some() && state.toplevels.focused != window {
+                state.focus_toplevel(window);
+            }
+            state.keybinds.bound_buttons.push(button);
+            run_keybind_action(state, action);
+            return
+        }
+    } else if let Some(idx) = state.keybinds.bound_buttons.iter().position(|&b| b == button) {
+        state.keybinds.bound_buttons.remove(idx);
+        return
+    }
+
+    // every policy focuses the clicked window
+    if event.state() == ButtonState::Pressed && !pointer.is_grabbed() {
+        if let Some((window, _, _)) = state.surface_under(pointer.current_location()) {
+            if state.toplevels.focused.as_ref() != Some(&window) {
+                state.focus_toplevel(Some(window));
+            }
+        }
+    }
+
     let event = ButtonEvent {
-        serial: 0.into(),
-        time: 0,
-        button: event.button_code(),
+        serial: SERIAL_COUNTER.next_serial(),
+        time: event.time_msec(),
+        button,
         state: event.state()
     };
     pointer.button(state, &event);
     pointer.frame(state);
-}
\ No newline at end of file
+}
+
+pub fn handle_pointer_axis<B: InputBackend>(state: &mut Waycrust, event: B::PointerAxisEvent) {
+    let pointer = match state.seat.get_pointer() {
+        Some(p) => p,
+        None => return
+    };
+    let config = &state.config.pointer;
+    let natural_scroll = config.natural_scroll;
+    let source = event.source();
+
+    let mut frame = AxisFrame::new(event.time_msec()).source(source);
+    for (axis, factor) in [
+        (Axis::Horizontal, config.horizontal_scroll_factor),
+        (Axis::Vertical, config.vertical_scroll_factor)
+    ] {
+        let factor = if natural_scroll { -factor } else { factor };
+        let v120 = event.amount_v120(axis);
+        // wheels without continuous value scroll 15 units per detent
+        let amount = event.amount(axis)
+            .or_else(|| v120.map(|v| v * 15.0 / 120.0))
+            .unwrap_or(0.0);
+
+        // scroll bound for the current modifiers never reaches the client
+        if state.handle_scroll_bind(axis, amount) {
+            continue
+        }
+
+        if amount != 0.0 {
+            let direction = match (event.relative_direction(axis), natural_scroll) {
+                (direction, false) => direction,
+                (AxisRelativeDirection::Identical, true) => AxisRelativeDirection::Inverted,
+                (AxisRelativeDirection::Inverted, true) => AxisRelativeDirection::Identical
+            };
+            frame = frame
+                .relative_direction(axis, direction)
+                .value(axis, amount * factor);
+            if let Some(v120) = v120 {
+                frame = frame.v120(axis, (v120 * factor) as i32);
+            }
+        }
+
+        // finger scrolling reports zero when the fingers are lifted, so kinetic scrolling can start
+        if source == AxisSource::Finger && event.amount(axis) == Some(0.0) {
+            frame = frame.stop(axis);
+        }
+    }
+
+    pointer.axis(state, frame);
+    pointer.frame(state);
+}
+
+/// Furthest point from `start` towards `start + delta` that is still inside, `start` has to be inside.
+fn clamp_motion(start: Point<f64, Logical>, delta: Point<f64, Logical>, inside: impl Fn(Point<f64, Logical>) -> bool) -> Point<f64, Logical> {
+    if inside(start + delta) {
+        return start + delta
+    }
+    // halving the step 16 times gets well below a pixel for any motion across the output
+    let (mut reached, mut missed) = (0.0, 1.0);
+    for _ in 0..16 {
+        let middle = (reached + missed) / 2.0;
+        if inside(start + delta.upscale(middle)) {
+            reached = middle;
+        } else {
+            missed = middle;
+        }
+    }
+    start + delta.upscale(reached)
+}
@@ -0,0 +1,114 @@
+use smithay::{backend::input::{Device, DeviceCapability, Event, InputBackend, ProximityState, TabletToolAxisEvent, TabletToolButtonEvent, TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent, TabletToolTipState}, utils::{Logical, Point, SERIAL_COUNTER}, wayland::tablet_manager::{TabletDescriptor, TabletSeatTrait}};
+
+use crate::compositor::Waycrust;
+
+pub fn handle_device_added<B: InputBackend>(state: &mut Waycrust, device: B::Device) {
+    if device.has_capability(DeviceCapability::TabletTool) {
+        let dh = state.display_handle.clone();
+        state.seat.tablet_seat().add_tablet::<Waycrust>(&dh, &TabletDescriptor::from(&device));
+    }
+}
+
+pub fn handle_device_removed<B: InputBackend>(state: &mut Waycrust, device: B::Device) {
+    if device.has_capability(DeviceCapability::TabletTool) {
+        let tablet_seat = state.seat.tablet_seat();
+        tablet_seat.remove_tablet(&TabletDescriptor::from(&device));
+        // tools go away with the last tablet
+        if tablet_seat.count_tablets() == 0 {
+            tablet_seat.clear_tools();
+        }
+    }
+}
+
+pub fn handle_tablet_tool_proximity<B: InputBackend>(state: &mut Waycrust, event: B::TabletToolProximityEvent) {
+    let Some(location) = state.tablet_location(&event) else {
+        return
+    };
+    let dh = state.display_handle.clone();
+    let tablet_seat = state.seat.tablet_seat();
+    let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+    let tool = tablet_seat.add_tool::<Waycrust>(state, &dh, &event.tool());
+
+    let under = state.surface_under(location).map(|(_, surface, loc)| (surface, loc));
+    match (event.state(), under, tablet) {
+        (ProximityState::In, under, tablet) => {
+            state.tool_location = Some(location);
+            if let (Some(under), Some(tablet)) = (under, tablet) {
+                tool.proximity_in(location, under, &tablet, SERIAL_COUNTER.next_serial(), event.time_msec());
+            }
+        }
+        (ProximityState::Out, _, _) => {
+            state.tool_location = None;
+            tool.proximity_out(event.time_msec());
+        }
+    }
+}
+
+pub fn handle_tablet_tool_axis<B: InputBackend>(state: &mut Waycrust, event: B::TabletToolAxisEvent) {
+    let Some(location) = state.tablet_location(&event) else {
+        return
+    };
+    let tablet_seat = state.seat.tablet_seat();
+    let tablet = tablet_seat.get_tablet(&TabletDescriptor::from(&event.device()));
+    let tool = tablet_seat.get_tool(&event.tool());
+
+    state.tool_location = Some(location);
+    let under = state.surface_under(location).map(|(_, surface, loc)| (surface, loc));
+    let (Some(tablet), Some(tool)) = (tablet, tool) else {
+        return
+    };
+
+    if event.pressure_has_changed() {
+        tool.pressure(event.pressure());
+    }
+    if event.distance_has_changed() {
+        tool.distance(event.distance());
+    }
+    if event.tilt_has_changed() {
+        tool.tilt(event.tilt());
+    }
+    if event.slider_has_changed() {
+        tool.slider_position(event.slider_position());
+    }
+    if event.rotation_has_changed() {
+        tool.rotation(event.rotation());
+    }
+    if event.wheel_has_changed() {
+        tool.wheel(event.wheel_delta(), event.wheel_delta_discrete());
+    }
+    tool.motion(location, under, &tablet, SERIAL_COUNTER.next_serial(), event.time_msec());
+}
+
+pub fn handle_tablet_tool_tip<B: InputBackend>(state: &mut Waycrust, event: B::TabletToolTipEvent) {
+    let Some(tool) = state.seat.tablet_seat().get_tool(&event.tool()) else {
+        return
+    };
+
+    match event.tip_state() {
+        TabletToolTipState::Down => {
+            // touching a window with the pen focuses it like a click does
+            if let Some(location) = state.tool_location {
+                if let Some((window, _, _)) = state.surface_under(location) {
+                    if state.toplevels.focused.as_ref() != Some(&window) {
+                        state.focus_toplevel(Some(window));
+                    }
+                }
+            }
+            tool.tip_down(SERIAL_COUNTER.next_serial(), event.time_msec());
+        }
+        TabletToolTipState::Up => tool.tip_up(event.time_msec())
+    }
+}
+
+pub fn handle_tablet_tool_button<B: InputBackend>(state: &mut Waycrust, event: B::TabletToolButtonEvent) {
+    if let Some(tool) = state.seat.tablet_seat().get_tool(&event.tool()) {
+        tool.button(event.button(), event.button_state(), SERIAL_COUNTER.next_serial(), event.time_msec());
+    }
+}
+
+impl Waycrust {
+    fn tablet_location<B: InputBackend>(&self, event: &impl TabletToolEvent<B>) -> Option<Point<f64, Logical>> {
+        let region = self.input_region(self.config.tablet.region)?;
+        Some(region.loc.to_f64() + event.position_transformed(region.size))
+    }
+}
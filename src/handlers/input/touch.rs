@@ -0,0 +1,98 @@
+use smithay::{backend::input::{AbsolutePositionEvent, Event, InputBackend, TouchEvent}, input::touch::{DownEvent, MotionEvent, UpEvent}, utils::{Logical, Point, SERIAL_COUNTER}};
+
+use crate::compositor::Waycrust;
+
+pub fn handle_touch_down<B: InputBackend>(state: &mut Waycrust, event: B::TouchDownEvent) {
+    let touch = match state.seat.get_touch() {
+        Some(t) => t,
+        None => return
+    };
+    let Some(location) = state.touch_location(&event) else {
+        return
+    };
+
+    let under = state.surface_under(location);
+    // touching a window focuses it like a click does
+    if let Some((window, _, _)) = under.as_ref() {
+        if state.toplevels.focused.as_ref() != Some(window) {
+            state.focus_toplevel(Some(window.clone()));
+        }
+    }
+
+    // the point keeps sending events to the surface it went down on
+    let focus = under.map(|(_, surface, loc)| (surface, loc));
+    state.touch_slots.insert(event.slot(), focus.clone());
+
+    touch.down(
+        state,
+        focus,
+        &DownEvent {
+            slot: event.slot(),
+            location,
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec()
+        }
+    );
+}
+
+pub fn handle_touch_motion<B: InputBackend>(state: &mut Waycrust, event: B::TouchMotionEvent) {
+    let touch = match state.seat.get_touch() {
+        Some(t) => t,
+        None => return
+    };
+    let Some(location) = state.touch_location(&event) else {
+        return
+    };
+    let focus = state.touch_slots.get(&event.slot()).cloned().flatten();
+    state.drag_touch_moved(event.slot(), location);
+
+    touch.motion(
+        state,
+        focus,
+        &MotionEvent {
+            slot: event.slot(),
+            location,
+            time: event.time_msec()
+        }
+    );
+}
+
+pub fn handle_touch_up<B: InputBackend>(state: &mut Waycrust, event: B::TouchUpEvent) {
+    let touch = match state.seat.get_touch() {
+        Some(t) => t,
+        None => return
+    };
+    state.touch_slots.remove(&event.slot());
+
+    touch.up(
+        state,
+        &UpEvent {
+            slot: event.slot(),
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec()
+        }
+    );
+}
+
+pub fn handle_touch_cancel<B: InputBackend>(state: &mut Waycrust, _event: B::TouchCancelEvent) {
+    let touch = match state.seat.get_touch() {
+        Some(t) => t,
+        None => return
+    };
+    // cancelling applies to the whole touch sequence, not just one point
+    state.touch_slots.clear();
+    touch.cancel(state);
+}
+
+pub fn handle_touch_frame(state: &mut Waycrust) {
+    if let Some(touch) = state.seat.get_touch() {
+        touch.frame(state);
+    }
+}
+
+impl Waycrust {
+    fn touch_location<B: InputBackend>(&self, event: &impl AbsolutePositionEvent<B>) -> Option<Point<f64, Logical>> {
+        let region = self.input_region(self.config.touch.region)?;
+        Some(region.loc.to_f64() + event.position_transformed(region.size))
+    }
+}
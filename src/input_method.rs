@@ -0,0 +1,54 @@
+use smithay::{desktop::utils::bbox_from_surface_tree, utils::{Logical, Point, Rectangle}, wayland::input_method::{InputMethodHandler, PopupSurface}};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use crate::compositor::Waycrust;
+
+
+
+impl InputMethodHandler for Waycrust {
+    fn new_popup(&mut self, surface: PopupSurface) {
+        self.input_method_popups.push(surface);
+    }
+
+    fn popup_repositioned(&mut self, _surface: PopupSurface) {}
+
+    fn dismiss_popup(&mut self, surface: PopupSurface) {
+        self.input_method_popups.retain(|p| *p != surface);
+    }
+
+    fn parent_geometry(&self, parent: &WlSurface) -> Rectangle<i32, Logical> {
+        let location = self.window_location(parent).unwrap_or_default();
+        let size = self.toplevels.properties.get(parent).and_then(|p| p.size)
+            .or(self.size)
+            .unwrap_or_default();
+        Rectangle::new(location, size)
+    }
+}
+
+impl Waycrust {
+    /// Where a window is drawn, hidden windows have no location.
+    pub fn window_location(&self, surface: &WlSurface) -> Option<Point<i32, Logical>> {
+        if self.toplevels.floating.iter().any(|t| t.wl_surface() == surface) {
+            return Some(self.toplevels.properties.get(surface).map(|p| p.location).unwrap_or_default())
+        }
+        self.toplevels.toplevels.front()
+            .filter(|t| t.wl_surface() == surface)
+            .map(|_| (0, 0).into())
+    }
+
+    /// Popups go below the text cursor of their window, or above it when there's no room left.
+    pub fn input_method_popup_location(&self, popup: &PopupSurface) -> Option<Point<i32, Logical>> {
+        let parent = popup.get_parent()?;
+        let cursor = popup.text_input_rectangle();
+        let origin = self.window_location(&parent.surface)? + cursor.loc;
+
+        let size = bbox_from_surface_tree(popup.wl_surface(), (0, 0)).size;
+        let output = self.size.unwrap_or_default();
+        let mut location = origin + Point::from((0, cursor.size.h));
+        if location.y + size.h > output.h {
+            location.y = origin.y - size.h;
+        }
+        location.x = location.x.min(output.w - size.w).max(0);
+        Some(location)
+    }
+}
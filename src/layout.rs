@@ -0,0 +1,75 @@
+use smithay::input::keyboard::Layout;
+
+use crate::compositor::Waycrust;
+
+
+
+impl Waycrust {
+    /// Layouts as given in the config of the active keyboard, in keymap order.
+    pub fn layout_names(&self) -> Vec<&str> {
+        self.keyboard_config().layout.split(',').map(str::trim).collect()
+    }
+
+    pub fn layout_name(&self) -> String {
+        self.layout_names().get(self.layout as usize)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| self.layout.to_string())
+    }
+
+    pub fn next_layout(&mut self) {
+        let count = self.layout_names().len() as u32;
+        self.set_layout((self.layout + 1) % count);
+    }
+
+    pub fn previous_layout(&mut self) {
+        let count = self.layout_names().len() as u32;
+        self.set_layout((self.layout + count - 1) % count);
+    }
+
+    /// Switches to a layout by its name in the config or by its index.
+    pub fn select_layout(&mut self, name: &str) {
+        let idx = self.layout_names().iter().position(|l| *l == name)
+            .map(|idx| idx as u32)
+            .or_else(|| name.parse().ok().filter(|&idx| idx < self.layout_names().len() as u32));
+        match idx {
+            Some(idx) => self.set_layout(idx),
+            None => println!("unknown layout {}", name)
+        }
+    }
+
+    pub fn set_layout(&mut self, idx: u32) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return
+        };
+        keyboard.with_xkb_state(self, |mut context| context.set_layout(Layout(idx)));
+        self.layout_changed(idx);
+    }
+
+    /// Records the active layout, it can also be switched by xkb itself through group options.
+    pub fn layout_changed(&mut self, idx: u32) {
+        if self.layout == idx {
+            return
+        }
+        self.layout = idx;
+
+        if self.config.keyboard.per_window_layout {
+            if let Some(props) = self.toplevels.focused.as_ref().and_then(|f| self.toplevels.properties.get_mut(f.wl_surface())) {
+                props.layout = Some(idx);
+            }
+        }
+        self.ipc_events.push(format!("LAYOUT {}", self.layout_name()));
+    }
+
+    /// Restores the layout last used in the newly focused window, windows that never switched keep the current one.
+    pub fn restore_window_layout(&mut self) {
+        if !self.config.keyboard.per_window_layout {
+            return
+        }
+        let layout = self.toplevels.focused.as_ref()
+            .and_then(|f| self.toplevels.properties.get(f.wl_surface()))
+            .and_then(|p| p.layout);
+        if let Some(layout) = layout {
+            self.set_layout(layout);
+        }
+    }
+}
@@ -5,12 +5,33 @@ pub mod handlers;
 pub mod config;
 pub mod render;
 pub mod socket;
+pub mod rules;
+pub mod scratchpad;
+pub mod cursor;
+pub mod layout;
+pub mod devices;
+pub mod virtual_input;
+pub mod input_method;
+pub mod clipboard;
+pub mod dnd;
 
 use compositor::Waycrust;
-use smithay::{delegate_xdg_shell, delegate_compositor, delegate_shm, delegate_seat, delegate_data_device};
+use smithay::{delegate_xdg_shell, delegate_compositor, delegate_shm, delegate_seat, delegate_data_device, delegate_cursor_shape, delegate_pointer_gestures, delegate_tablet_manager, delegate_pointer_constraints, delegate_relative_pointer, delegate_keyboard_shortcuts_inhibit, delegate_virtual_keyboard_manager, delegate_input_method_manager, delegate_text_input_manager, delegate_primary_selection, delegate_data_control, delegate_ext_data_control};
 // Macros used to delegate protocol handling to types in the app state.
 delegate_xdg_shell!(Waycrust);
 delegate_compositor!(Waycrust);
 delegate_shm!(Waycrust);
 delegate_seat!(Waycrust);
-delegate_data_device!(Waycrust);
\ No newline at end of file
+delegate_data_device!(Waycrust);
+delegate_cursor_shape!(Waycrust);
+delegate_pointer_gestures!(Waycrust);
+delegate_tablet_manager!(Waycrust);
+delegate_pointer_constraints!(Waycrust);
+delegate_relative_pointer!(Waycrust);
+delegate_keyboard_shortcuts_inhibit!(Waycrust);
+delegate_virtual_keyboard_manager!(Waycrust);
+delegate_input_method_manager!(Waycrust);
+delegate_text_input_manager!(Waycrust);
+delegate_primary_selection!(Waycrust);
+delegate_data_control!(Waycrust);
+delegate_ext_data_control!(Waycrust);
\ No newline at end of file
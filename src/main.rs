@@ -1,10 +1,7 @@
 use waycrust::{
     compositor::Waycrust, 
     handlers::{
-        actions::handle_actions, input::{
-            keyboard::handle_keyboard_event, 
-            pointer::{handle_pointer_button, handle_pointer_movement}
-        }, window::window_resize_handler
+        actions::handle_actions, input::{handle_input_event, touch::handle_touch_frame}, window::window_resize_handler
     }, 
     render::render_screen, socket::ActionSocket
 };
@@ -13,9 +10,12 @@ use ::winit::platform::pump_events::PumpStatus;
 use smithay::{
     backend::{
         input::InputEvent,
+        libinput::{LibinputInputBackend, LibinputSessionInterface},
         renderer::gles::GlesRenderer,
+        session::{Event as SessionEvent, Session, libseat::LibSeatSession},
         winit::{self, WinitEvent},
-    }
+    },
+    reexports::{calloop::{EventLoop, LoopHandle}, input::Libinput}
 };
 use wayland_server::ListeningSocket;
 
@@ -32,51 +32,67 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 pub fn run_winit() -> Result<(), Box<dyn std::error::Error>> {
-    let (mut state, mut display) = Waycrust::init()?;
+    let mut event_loop: EventLoop<Waycrust> = EventLoop::try_new()?;
+    let (mut state, mut display) = Waycrust::init(event_loop.handle())?;
     
     let listener = ListeningSocket::bind("wayland-5").unwrap();
     
     let (mut backend, mut winit) = winit::init::<GlesRenderer>()?;
 
     state.size = Some(backend.window_size().to_logical(1));
+    // the cursor is drawn by the compositor itself
+    backend.window().set_cursor_visible(false);
     
     let start_time = std::time::Instant::now();
     
-    let _keyboard = state.seat.add_keyboard((&state.config.keyboard).into(), 200, 200);
+    let keyboard_config = &state.config.keyboard;
+    let _keyboard = state.seat.add_keyboard(keyboard_config.into(), keyboard_config.repeat_delay, keyboard_config.repeat_rate);
+    state.apply_lock_keys();
+    state.check_keybind_modifiers();
     let _pointer = state.seat.add_pointer();
+    let _touch = state.seat.add_touch();
     
     unsafe {
         std::env::set_var("WAYLAND_DISPLAY", "wayland-5");
     }
 
-    let action_socket = ActionSocket::new("/tmp/waycrust.sock")?;
+    let mut action_socket = ActionSocket::new("/tmp/waycrust.sock")?;
+
+    let libinput = state.config.libinput;
+    if libinput {
+        init_libinput(&event_loop.handle())?;
+    }
     
     loop {
         let status = winit.dispatch_new_events(|event| match event {
             WinitEvent::Resized { size, .. } => {
                 window_resize_handler(&mut state, size);
             }
-            WinitEvent::Input(event) => match event {
-                InputEvent::Keyboard { event } => {
-                    handle_keyboard_event(&mut state, event);
+            // the same devices would be reported twice
+            WinitEvent::Input(_) if libinput => {}
+            WinitEvent::Input(event) => {
+                // winit reports no touch frames, so every touch event is a frame of its own
+                let touch = matches!(event,
+                    InputEvent::TouchDown { .. } | InputEvent::TouchMotion { .. } | InputEvent::TouchUp { .. }
+                );
+                handle_input_event(&mut state, event);
+                if touch {
+                    handle_touch_frame(&mut state);
                 }
-                InputEvent::PointerMotionAbsolute { event } => {
-                    handle_pointer_movement(&mut state, event);
-                }
-                InputEvent::PointerButton { event } => {
-                    handle_pointer_button(&mut state, event);
-                }
-                _ => {}
-            },
+            }
             WinitEvent::CloseRequested => {
 
             }
             _ => (),
         });
 
-        for action in action_socket.pool() {
-            handle_actions(&mut state, action);
-        }
+        event_loop.dispatch(Some(std::time::Duration::ZERO), &mut state)?;
+        action_socket.pool(|action| handle_actions(&mut state, action));
+        state.check_mode_timeout();
+        state.repeat_keybind();
+        state.persist_selections();
+        state.check_drag_edge();
+        action_socket.broadcast(state.ipc_events.drain(..));
 
         match status {
             PumpStatus::Continue => (),
@@ -91,3 +107,28 @@ pub fn run_winit() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
+/// Reads input devices through libinput on the seat of the current session.
+fn init_libinput(handle: &LoopHandle<'static, Waycrust>) -> Result<(), Box<dyn std::error::Error>> {
+    let (session, notifier) = LibSeatSession::new()?;
+    let mut libinput = Libinput::new_with_udev::<LibinputSessionInterface<LibSeatSession>>(session.clone().into());
+    libinput.udev_assign_seat(&session.seat()).map_err(|_| "cannot assign libinput to the seat")?;
+
+    handle.insert_source(LibinputInputBackend::new(libinput.clone()), |event, _, state| {
+        match &event {
+            InputEvent::DeviceAdded { device } => state.add_libinput_device(device.clone()),
+            InputEvent::DeviceRemoved { device } => state.remove_libinput_device(device),
+            _ => {}
+        }
+        handle_input_event(state, event);
+    }).map_err(|err| err.error)?;
+    // devices are released while another session has the seat, like after a VT switch
+    handle.insert_source(notifier, move |event, _, _| match event {
+        SessionEvent::PauseSession => libinput.suspend(),
+        SessionEvent::ActivateSession => {
+            if libinput.resume().is_err() {
+                println!("cannot resume libinput");
+            }
+        }
+    }).map_err(|err| err.error)?;
+    Ok(())
+}
@@ -4,13 +4,20 @@ use smithay::{backend::{
     renderer::{
         Color32F, Frame, Renderer, element::{
             Kind, 
+            memory::MemoryRenderBufferRenderElement,
             surface::{WaylandSurfaceRenderElement, render_elements_from_surface_tree}}, gles::GlesRenderer, utils::draw_render_elements
         }, 
     winit::WinitGraphicsBackend
-}, utils::{Physical, Rectangle, Transform}, wayland::compositor::{SurfaceAttributes, TraversalAction, with_surface_tree_downward}};
-use wayland_server::{Display, ListeningSocket, protocol::wl_surface};
+}, input::pointer::{CursorImageStatus, CursorImageSurfaceData}, render_elements, utils::{Logical, Physical, Point, Rectangle, Transform}, wayland::compositor::{SurfaceAttributes, TraversalAction, with_states, with_surface_tree_downward}};
+use wayland_server::{Display, ListeningSocket, Resource, protocol::wl_surface};
 
-use crate::compositor::{ClientState, Waycrust};
+use crate::{compositor::{ClientState, Waycrust}, cursor::Cursor};
+
+render_elements! {
+    pub WaycrustRenderElement<=GlesRenderer>;
+    Surface=WaylandSurfaceRenderElement<GlesRenderer>,
+    Memory=MemoryRenderBufferRenderElement<GlesRenderer>,
+}
 
 pub fn render_screen(
     state: &mut Waycrust, 
@@ -22,17 +29,65 @@ pub fn render_screen(
     let size = backend.window_size();
     let (renderer, mut framebuffer) = backend.bind().unwrap();
     
-    let to_render: Vec<WaylandSurfaceRenderElement<GlesRenderer>> = match state.toplevels.focused {
-        Some(ref top) => render_elements_from_surface_tree(
+    // elements are drawn front to back, so the cursor goes first followed by the drag icon, input method popups
+    // and floating windows starting from the topmost one
+    let mut to_render: Vec<WaycrustRenderElement> = vec![];
+    // the tablet tool has a cursor of its own, so it doesn't need to move the pointer to be seen
+    if let Some(location) = state.tool_location.filter(|_| !state.config.cursor.hidden) {
+        to_render.extend(cursor_elements(renderer, &mut state.cursor, &mut state.tool_cursor_status, location, time));
+    }
+    if let Some(pointer) = state.seat.get_pointer() {
+        if !state.config.cursor.hidden {
+            to_render.extend(cursor_elements(renderer, &mut state.cursor, &mut state.cursor_status, pointer.current_location(), time));
+        }
+        if let (Some(drag), Some(location)) = (state.drag.as_ref(), state.drag_location()) {
+            if let Some(icon) = drag.icon.as_ref().filter(|icon| icon.alive()) {
+                to_render.extend(render_elements_from_surface_tree(
+                    renderer,
+                    icon,
+                    (location.to_i32_round() + drag.icon_offset).to_physical(1),
+                    1.0,
+                    1.0,
+                    Kind::Unspecified
+                ).into_iter().map(WaycrustRenderElement::from));
+            }
+        }
+    }
+    for popup in state.input_method_popups.iter() {
+        let Some(location) = state.input_method_popup_location(popup) else {
+            continue
+        };
+        to_render.extend(render_elements_from_surface_tree(
+            renderer,
+            popup.wl_surface(),
+            location.to_physical(1),
+            1.0,
+            1.0,
+            Kind::Unspecified
+        ).into_iter().map(WaycrustRenderElement::from));
+    }
+    for top in state.toplevels.floating.iter().rev() {
+        let props = state.toplevels.properties.get(top.wl_surface());
+        to_render.extend(render_elements_from_surface_tree(
             renderer,
             top.wl_surface(),
-            (0, 0),
+            props.map(|p| p.location).unwrap_or_default().to_physical(1),
             1.0,
+            props.map_or(1.0, |p| p.opacity),
+            Kind::Unspecified
+        ).into_iter().map(WaycrustRenderElement::from));
+    }
+    if let Some(top) = state.toplevels.toplevels.front() {
+        let props = state.toplevels.properties.get(top.wl_surface());
+        to_render.extend(render_elements_from_surface_tree(
+            renderer,
+            top.wl_surface(),
+            (0, 0),
             1.0,
+            props.map_or(1.0, |p| p.opacity),
             Kind::Unspecified
-        ),
-        None => vec![]
-    };
+        ).into_iter().map(WaycrustRenderElement::from));
+    }
     
     let damage = Rectangle::from_size(size);
 
@@ -50,15 +105,28 @@ pub fn render_screen(
     // We rely on the nested compositor to do the sync for us
     let _ = frame.finish().unwrap();
 
-    if let Some(ref surface) = state.toplevels.focused {
+    for surface in state.toplevels.toplevels.front().into_iter().chain(state.toplevels.floating.iter()) {
         send_frames_surface_tree(surface.wl_surface(), time);
     }
+    for status in [&state.cursor_status, &state.tool_cursor_status] {
+        if let CursorImageStatus::Surface(surface) = status {
+            send_frames_surface_tree(surface, time);
+        }
+    }
+    for popup in state.input_method_popups.iter() {
+        send_frames_surface_tree(popup.wl_surface(), time);
+    }
+    if let Some(icon) = state.drag.as_ref().and_then(|d| d.icon.as_ref()) {
+        send_frames_surface_tree(icon, time);
+    }
 
     if let Some(stream) = listener.accept()? {
         println!("Got a client: {:?}", stream);
 
-        let _ = display.handle()
-               .insert_client(stream, Arc::new(ClientState::default())).unwrap();
+        let client_state = Arc::new(ClientState::default());
+        let client = display.handle()
+               .insert_client(stream, client_state.clone()).unwrap();
+        client_state.set_privileged(state.is_privileged_process(&client));
     }
 
     display.dispatch_clients(state)?;
@@ -67,6 +135,51 @@ pub fn render_screen(
     Ok(damage)
 }
 
+fn cursor_elements(
+    renderer: &mut GlesRenderer,
+    cursor: &mut Cursor,
+    status: &mut CursorImageStatus,
+    location: Point<f64, Logical>,
+    time: u32
+) -> Vec<WaycrustRenderElement> {
+    if let CursorImageStatus::Surface(ref surface) = *status {
+        if !surface.alive() {
+            *status = CursorImageStatus::default_named();
+        }
+    }
+
+    match *status {
+        CursorImageStatus::Hidden => vec![],
+        CursorImageStatus::Surface(ref surface) => {
+            let hotspot = with_states(surface, |states| {
+                states.data_map.get::<CursorImageSurfaceData>().unwrap().lock().unwrap().hotspot
+            });
+            render_elements_from_surface_tree(
+                renderer,
+                surface,
+                (location - hotspot.to_f64()).to_physical(1.0).to_i32_round(),
+                1.0,
+                1.0,
+                Kind::Cursor
+            ).into_iter().map(WaycrustRenderElement::from).collect()
+        }
+        CursorImageStatus::Named(icon) => {
+            let Some(frame) = cursor.icon_frame(icon, time) else {
+                return vec![]
+            };
+            MemoryRenderBufferRenderElement::from_buffer(
+                renderer,
+                (location - frame.hotspot.to_f64()).to_physical(1.0),
+                &frame.buffer,
+                None,
+                None,
+                None,
+                Kind::Cursor
+            ).ok().into_iter().map(WaycrustRenderElement::from).collect()
+        }
+    }
+}
+
 fn send_frames_surface_tree(surface: &wl_surface::WlSurface, time: u32) {
     with_surface_tree_downward(
         surface,
@@ -89,4 +202,3 @@ fn send_frames_surface_tree(surface: &wl_surface::WlSurface, time: u32) {
     );
 }
 
-
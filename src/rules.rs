@@ -0,0 +1,60 @@
+use smithay::wayland::{compositor::with_states, shell::xdg::{ToplevelSurface, XdgToplevelSurfaceData}};
+
+use crate::compositor::Waycrust;
+
+
+
+pub fn toplevel_identity(surface: &ToplevelSurface) -> (String, String) {
+    with_states(surface.wl_surface(), |states| {
+        let data = states.data_map.get::<XdgToplevelSurfaceData>().unwrap().lock().unwrap();
+        (
+            data.app_id.clone().unwrap_or_default(),
+            data.title.clone().unwrap_or_default()
+        )
+    })
+}
+
+impl Waycrust {
+    pub fn apply_window_rules(&mut self, surface: &ToplevelSurface) {
+        let (app_id, title) = toplevel_identity(surface);
+
+        let mut matched = vec![];
+        let mut floating = false;
+        let mut fullscreen = false;
+        let mut size = None;
+        let mut opacity = 1.0;
+        let mut no_focus = false;
+
+        for (idx, rule) in self.config.window_rules.iter().enumerate() {
+            if !rule.matches(&app_id, &title) {
+                continue
+            }
+            matched.push(rule.name.clone().unwrap_or_else(|| format!("#{}", idx)));
+
+            // later rules override properties set by earlier ones
+            if let Some(f) = rule.floating { floating = f }
+            if let Some(f) = rule.fullscreen { fullscreen = f }
+            if let Some((w, h)) = rule.size { size = Some((w, h).into()) }
+            if let Some(o) = rule.opacity { opacity = o.clamp(0.0, 1.0) }
+            if let Some(n) = rule.no_focus { no_focus = n }
+        }
+
+        let props = self.toplevels.properties.entry(surface.wl_surface().clone()).or_default();
+        props.matched_rules = matched;
+        props.opacity = opacity;
+        props.no_focus = no_focus;
+        let scratchpad = props.scratchpad;
+        let resized = size.is_some() && props.size != size;
+        if resized {
+            props.size = size;
+        }
+
+        let floating = floating && !fullscreen;
+        // scratchpad windows are shown and hidden only by the scratchpad itself
+        if !scratchpad && floating != self.toplevels.floating.contains(surface) {
+            self.set_floating(surface, floating);
+        } else if resized {
+            self.configure_toplevel(surface);
+        }
+    }
+}
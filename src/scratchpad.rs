@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+
+use smithay::wayland::shell::xdg::ToplevelSurface;
+
+use crate::{comp_utils::spawn_command, compositor::Waycrust, rules::toplevel_identity};
+
+// commands that don't map a window by then can be started again
+const PENDING_SCRATCHPAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+
+impl Waycrust {
+    pub fn move_to_scratchpad(&mut self) {
+        let Some(surface) = self.toplevels.focused.clone() else {
+            return
+        };
+
+        if let Some(props) = self.toplevels.properties.get_mut(surface.wl_surface()) {
+            props.scratchpad = true;
+        }
+        self.hide_scratchpad(&surface);
+    }
+
+    /// Shows the first hidden unnamed scratchpad window, or hides the shown ones.
+    pub fn toggle_scratchpad(&mut self) {
+        let shown: Vec<ToplevelSurface> = self.toplevels.floating.iter()
+            .filter(|s| self.is_scratchpad(s) && !self.is_named_scratchpad(s))
+            .cloned()
+            .collect();
+
+        if !shown.is_empty() {
+            for surface in shown {
+                self.hide_scratchpad(&surface);
+            }
+        } else if let Some(surface) = self.toplevels.scratchpad.iter().find(|s| !self.is_named_scratchpad(s)).cloned() {
+            self.show_scratchpad(&surface);
+        }
+    }
+
+    pub fn toggle_named_scratchpad(&mut self, name: &str) {
+        if let Some(surface) = self.toplevels.named_scratchpads.get(name).cloned() {
+            if self.toplevels.floating.contains(&surface) {
+                self.hide_scratchpad(&surface);
+            } else {
+                self.show_scratchpad(&surface);
+            }
+            return
+        }
+
+        self.expire_pending_scratchpads();
+        if self.toplevels.pending_scratchpads.iter().any(|(n, _)| n == name) {
+            return
+        }
+        match self.config.scratchpads.iter().find(|s| s.name == name) {
+            Some(scratchpad) => {
+                if spawn_command(&scratchpad.exec) {
+                    self.toplevels.pending_scratchpads.push((name.to_string(), Instant::now()));
+                }
+            }
+            None => println!("unknown scratchpad {}", name)
+        }
+    }
+
+    /// Claims a freshly mapped window for a named scratchpad that is waiting for its command to start.
+    pub fn claim_scratchpad(&mut self, surface: &ToplevelSurface) {
+        self.expire_pending_scratchpads();
+        if self.toplevels.pending_scratchpads.is_empty() || self.is_scratchpad(surface) {
+            return
+        }
+
+        let (app_id, _) = toplevel_identity(surface);
+        let Some(idx) = self.toplevels.pending_scratchpads.iter().position(|(name, _)| {
+            self.config.scratchpads.iter().any(|s| &s.name == name && s.app_id.matches(&app_id))
+        }) else {
+            return
+        };
+
+        let (name, _) = self.toplevels.pending_scratchpads.remove(idx);
+        if let Some(props) = self.toplevels.properties.get_mut(surface.wl_surface()) {
+            props.scratchpad = true;
+        }
+        self.toplevels.named_scratchpads.insert(name, surface.clone());
+        self.show_scratchpad(surface);
+    }
+
+    fn expire_pending_scratchpads(&mut self) {
+        self.toplevels.pending_scratchpads.retain(|(_, started)| started.elapsed() < PENDING_SCRATCHPAD_TIMEOUT);
+    }
+
+    fn show_scratchpad(&mut self, surface: &ToplevelSurface) {
+        self.toplevels.scratchpad.retain(|s| s != surface);
+        self.set_floating(surface, true);
+        self.focus_toplevel(Some(surface.clone()));
+    }
+
+    fn hide_scratchpad(&mut self, surface: &ToplevelSurface) {
+        self.toplevels.toplevels.retain(|s| s != surface);
+        self.toplevels.floating.retain(|s| s != surface);
+        if !self.toplevels.scratchpad.contains(surface) {
+            self.toplevels.scratchpad.push(surface.clone());
+        }
+
+        if self.toplevels.focused.as_ref() == Some(surface) {
+            let next = self.toplevels.floating.last()
+                .or(self.toplevels.toplevels.front())
+                .cloned();
+            self.focus_toplevel(next);
+        }
+    }
+
+    fn is_scratchpad(&self, surface: &ToplevelSurface) -> bool {
+        self.toplevels.properties.get(surface.wl_surface()).is_some_and(|p| p.scratchpad)
+    }
+
+    fn is_named_scratchpad(&self, surface: &ToplevelSurface) -> bool {
+        self.toplevels.named_scratchpads.values().any(|s| s == surface)
+    }
+}
@@ -1,15 +1,31 @@
-use std::os::unix::net::UnixListener;
-use std::io::{BufRead, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 
+use crate::handlers::input::gestures::GestureKind;
+
 pub enum Action {
     Exit,
     Exec(String),
-    Flip
+    Flip,
+    Rules,
+    Mode(Option<String>),
+    Layout(Option<String>),
+    Repeat(Option<(i32, i32)>),
+    Reload,
+    /// Synthetic gesture going through the same recognition as touchpad ones.
+    Gesture {
+        kind: GestureKind,
+        fingers: u32,
+        delta: (f64, f64),
+        scale: f64
+    },
+    Subscribe
 }
 
 pub struct ActionSocket {
-    listener: UnixListener
+    listener: UnixListener,
+    subscribers: Vec<UnixStream>
 }
 
 impl ActionSocket {
@@ -17,22 +33,39 @@ impl ActionSocket {
         let _ = std::fs::remove_file(&path);
         let listener = UnixListener::bind(path)?;
         listener.set_nonblocking(true)?;
-        Ok(Self {listener})
+        Ok(Self {listener, subscribers: vec![]})
     }
 
-    pub fn pool(&self) -> Vec<Action> {
-        let mut actions = vec![];
-
+    /// Passes every received action to `handler`, writing back whatever it replies with.
+    /// Connection sending SUBSCRIBE is kept open and receives events from `broadcast`.
+    pub fn pool(&mut self, mut handler: impl FnMut(Action) -> Option<String>) {
         if let Ok((stream, _)) = self.listener.accept() {
+            let mut writer = stream.try_clone().ok();
             let reader = BufReader::new(stream);
             for line in reader.lines().flatten() {
-                if let Some(action) = parse_action(&line) {
-                    actions.push(action);
+                let Some(action) = parse_action(&line) else {
+                    continue
+                };
+                if let Action::Subscribe = action {
+                    if let Some(writer) = writer.take() {
+                        // a subscriber that stops reading is dropped rather than blocking the compositor
+                        if writer.set_nonblocking(true).is_ok() {
+                            self.subscribers.push(writer);
+                        }
+                    }
+                    break
+                }
+                if let (Some(reply), Some(writer)) = (handler(action), writer.as_mut()) {
+                    let _ = writeln!(writer, "{}", reply);
                 }
             }
         }
+    }
 
-        actions
+    pub fn broadcast(&mut self, events: impl IntoIterator<Item = String>) {
+        for event in events {
+            self.subscribers.retain_mut(|s| writeln!(s, "{}", event).is_ok());
+        }
     }
 }
 
@@ -50,6 +83,39 @@ fn parse_action(input: &str) -> Option<Action> {
         "EXIT" => Some(Action::Exit),
         "EXEC" => Some(Action::Exec(rest.to_string())),
         "FLIP" => Some(Action::Flip),
+        "RULES" => Some(Action::Rules),
+        "MODE" => Some(Action::Mode((!rest.is_empty()).then(|| rest.to_string()))),
+        "LAYOUT" => Some(Action::Layout((!rest.is_empty()).then(|| rest.to_string()))),
+        "REPEAT" => {
+            let mut args = rest.split_whitespace().map(str::parse::<i32>);
+            match (args.next(), args.next()) {
+                (None, _) => Some(Action::Repeat(None)),
+                (Some(Ok(delay)), Some(Ok(rate))) if delay >= 0 && rate >= 0 => Some(Action::Repeat(Some((delay, rate)))),
+                _ => None
+            }
+        }
+        "RELOAD" => Some(Action::Reload),
+        "GESTURE" => parse_gesture(rest),
+        "SUBSCRIBE" => Some(Action::Subscribe),
         _ => None
     }
-}
\ No newline at end of file
+}
+
+/// `swipe <fingers> <dx> <dy>`, `pinch <fingers> <scale>` or `hold <fingers>`
+fn parse_gesture(input: &str) -> Option<Action> {
+    let mut args = input.split_whitespace();
+    let kind = match args.next()? {
+        "swipe" => GestureKind::Swipe,
+        "pinch" => GestureKind::Pinch,
+        "hold" => GestureKind::Hold,
+        _ => return None
+    };
+    let fingers = args.next()?.parse().ok()?;
+    let mut numbers = args.map(str::parse::<f64>);
+    let (delta, scale) = match kind {
+        GestureKind::Swipe => ((numbers.next()?.ok()?, numbers.next()?.ok()?), 1.0),
+        GestureKind::Pinch => ((0.0, 0.0), numbers.next()?.ok()?),
+        GestureKind::Hold => ((0.0, 0.0), 1.0)
+    };
+    Some(Action::Gesture { kind, fingers, delta, scale })
+}
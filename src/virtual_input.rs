@@ -0,0 +1,407 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use smithay::{
+    backend::input::{AbsolutePositionEvent, Axis, AxisRelativeDirection, AxisSource, ButtonState, Device, DeviceCapability, Event, InputBackend, InputEvent, KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionAbsoluteEvent, PointerMotionEvent, UnusedEvent},
+    input::keyboard::{KeyboardHandle, ModifiersState},
+    reexports::wayland_protocols_wlr::virtual_pointer::v1::server::{zwlr_virtual_pointer_manager_v1::{self, ZwlrVirtualPointerManagerV1}, zwlr_virtual_pointer_v1::{self, ZwlrVirtualPointerV1}},
+    wayland::virtual_keyboard::VirtualKeyboardHandler
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, WEnum, backend::GlobalId, protocol::wl_pointer};
+use xkbcommon::xkb::{Keycode, ModMask};
+
+use crate::{compositor::{Waycrust, client_privileged}, handlers::input::handle_input_event};
+
+
+
+/// Input synthesized by clients, handled like input of a real backend.
+pub struct VirtualInputBackend;
+
+impl InputBackend for VirtualInputBackend {
+    type Device = VirtualDevice;
+    type KeyboardKeyEvent = VirtualKeyEvent;
+    type PointerAxisEvent = VirtualAxisEvent;
+    type PointerButtonEvent = VirtualButtonEvent;
+    type PointerMotionEvent = VirtualMotionEvent;
+    type PointerMotionAbsoluteEvent = VirtualMotionAbsoluteEvent;
+    type GestureSwipeBeginEvent = UnusedEvent;
+    type GestureSwipeUpdateEvent = UnusedEvent;
+    type GestureSwipeEndEvent = UnusedEvent;
+    type GesturePinchBeginEvent = UnusedEvent;
+    type GesturePinchUpdateEvent = UnusedEvent;
+    type GesturePinchEndEvent = UnusedEvent;
+    type GestureHoldBeginEvent = UnusedEvent;
+    type GestureHoldEndEvent = UnusedEvent;
+    type TouchDownEvent = UnusedEvent;
+    type TouchUpEvent = UnusedEvent;
+    type TouchMotionEvent = UnusedEvent;
+    type TouchCancelEvent = UnusedEvent;
+    type TouchFrameEvent = UnusedEvent;
+    type TabletToolAxisEvent = UnusedEvent;
+    type TabletToolProximityEvent = UnusedEvent;
+    type TabletToolTipEvent = UnusedEvent;
+    type TabletToolButtonEvent = UnusedEvent;
+    type SwitchToggleEvent = UnusedEvent;
+    type SpecialEvent = UnusedEvent;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VirtualDevice {
+    Keyboard,
+    Pointer
+}
+
+impl Device for VirtualDevice {
+    fn id(&self) -> String {
+        format!("virtual-{}", self.name())
+    }
+
+    fn name(&self) -> String {
+        match self {
+            VirtualDevice::Keyboard => "keyboard",
+            VirtualDevice::Pointer => "pointer"
+        }.to_string()
+    }
+
+    fn has_capability(&self, capability: DeviceCapability) -> bool {
+        matches!(
+            (self, capability),
+            (VirtualDevice::Keyboard, DeviceCapability::Keyboard) | (VirtualDevice::Pointer, DeviceCapability::Pointer)
+        )
+    }
+
+    fn usb_id(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn syspath(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub struct VirtualKeyEvent {
+    keycode: Keycode,
+    state: KeyState,
+    time: u32
+}
+
+impl Event<VirtualInputBackend> for VirtualKeyEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        VirtualDevice::Keyboard
+    }
+}
+
+impl KeyboardKeyEvent<VirtualInputBackend> for VirtualKeyEvent {
+    fn key_code(&self) -> Keycode {
+        self.keycode
+    }
+
+    fn state(&self) -> KeyState {
+        self.state
+    }
+
+    fn count(&self) -> u32 {
+        match self.state {
+            KeyState::Pressed => 1,
+            KeyState::Released => 0
+        }
+    }
+}
+
+pub struct VirtualMotionEvent {
+    dx: f64,
+    dy: f64,
+    time: u32
+}
+
+impl Event<VirtualInputBackend> for VirtualMotionEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        VirtualDevice::Pointer
+    }
+}
+
+impl PointerMotionEvent<VirtualInputBackend> for VirtualMotionEvent {
+    fn delta_x(&self) -> f64 {
+        self.dx
+    }
+
+    fn delta_y(&self) -> f64 {
+        self.dy
+    }
+
+    fn delta_x_unaccel(&self) -> f64 {
+        self.dx
+    }
+
+    fn delta_y_unaccel(&self) -> f64 {
+        self.dy
+    }
+}
+
+/// Position within an extent chosen by the client, scaled to the output.
+pub struct VirtualMotionAbsoluteEvent {
+    x: u32,
+    y: u32,
+    x_extent: u32,
+    y_extent: u32,
+    time: u32
+}
+
+impl Event<VirtualInputBackend> for VirtualMotionAbsoluteEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        VirtualDevice::Pointer
+    }
+}
+
+impl AbsolutePositionEvent<VirtualInputBackend> for VirtualMotionAbsoluteEvent {
+    fn x(&self) -> f64 {
+        self.x as f64
+    }
+
+    fn y(&self) -> f64 {
+        self.y as f64
+    }
+
+    fn x_transformed(&self, width: i32) -> f64 {
+        self.x as f64 * width as f64 / self.x_extent.max(1) as f64
+    }
+
+    fn y_transformed(&self, height: i32) -> f64 {
+        self.y as f64 * height as f64 / self.y_extent.max(1) as f64
+    }
+}
+
+impl PointerMotionAbsoluteEvent<VirtualInputBackend> for VirtualMotionAbsoluteEvent {}
+
+pub struct VirtualButtonEvent {
+    button: u32,
+    state: ButtonState,
+    time: u32
+}
+
+impl Event<VirtualInputBackend> for VirtualButtonEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        VirtualDevice::Pointer
+    }
+}
+
+impl PointerButtonEvent<VirtualInputBackend> for VirtualButtonEvent {
+    fn button_code(&self) -> u32 {
+        self.button
+    }
+
+    fn state(&self) -> ButtonState {
+        self.state
+    }
+}
+
+/// Scrolling collected until the client ends the frame, indexed by axis.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualAxisEvent {
+    source: Option<AxisSource>,
+    amount: [Option<f64>; 2],
+    v120: [Option<f64>; 2],
+    time: u32
+}
+
+impl Event<VirtualInputBackend> for VirtualAxisEvent {
+    fn time(&self) -> u64 {
+        self.time as u64 * 1000
+    }
+
+    fn device(&self) -> VirtualDevice {
+        VirtualDevice::Pointer
+    }
+}
+
+impl PointerAxisEvent<VirtualInputBackend> for VirtualAxisEvent {
+    fn amount(&self, axis: Axis) -> Option<f64> {
+        self.amount[axis_index(axis)]
+    }
+
+    fn amount_v120(&self, axis: Axis) -> Option<f64> {
+        self.v120[axis_index(axis)]
+    }
+
+    fn source(&self) -> AxisSource {
+        self.source.unwrap_or(AxisSource::Wheel)
+    }
+
+    fn relative_direction(&self, _axis: Axis) -> AxisRelativeDirection {
+        AxisRelativeDirection::Identical
+    }
+}
+
+fn axis_index(axis: Axis) -> usize {
+    match axis {
+        Axis::Horizontal => 0,
+        Axis::Vertical => 1
+    }
+}
+
+impl Waycrust {
+    fn handle_virtual_input(&mut self, event: InputEvent<VirtualInputBackend>) {
+        self.virtual_input = true;
+        handle_input_event(self, event);
+        self.virtual_input = false;
+    }
+
+    /// Remaps and binds apply to virtual input only when configured.
+    pub fn bindings_enabled(&self) -> bool {
+        !self.virtual_input || self.config.virtual_input.bindings
+    }
+}
+
+impl VirtualKeyboardHandler for Waycrust {
+    fn on_keyboard_event(&mut self, keycode: Keycode, state: KeyState, time: u32, _keyboard: KeyboardHandle<Self>) {
+        self.handle_virtual_input(InputEvent::Keyboard {
+            event: VirtualKeyEvent { keycode, state, time }
+        });
+    }
+
+    fn on_keyboard_modifiers(&mut self, depressed: ModMask, latched: ModMask, locked: ModMask, keyboard: KeyboardHandle<Self>) {
+        let mut mods: ModifiersState = keyboard.modifier_state();
+        mods.serialized.depressed = depressed;
+        mods.serialized.latched = latched;
+        mods.serialized.locked = locked;
+        keyboard.set_modifier_state(mods);
+    }
+}
+
+/// The `zwlr_virtual_pointer_manager_v1` global.
+pub struct VirtualPointerManagerState {
+    _global: GlobalId
+}
+
+impl VirtualPointerManagerState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        Self {
+            _global: dh.create_global::<Waycrust, ZwlrVirtualPointerManagerV1, ()>(2, ())
+        }
+    }
+}
+
+impl GlobalDispatch<ZwlrVirtualPointerManagerV1, ()> for Waycrust {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrVirtualPointerManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>
+    ) {
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, _global_data: &()) -> bool {
+        client_privileged(&client)
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerManagerV1, ()> for Waycrust {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &ZwlrVirtualPointerManagerV1,
+        request: zwlr_virtual_pointer_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>
+    ) {
+        match request {
+            // there is a single seat and output, so the ones requested don't matter
+            zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointer { id, .. }
+            | zwlr_virtual_pointer_manager_v1::Request::CreateVirtualPointerWithOutput { id, .. } => {
+                data_init.init(id, Mutex::new(VirtualAxisEvent::default()));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerV1, Mutex<VirtualAxisEvent>> for Waycrust {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ZwlrVirtualPointerV1,
+        request: zwlr_virtual_pointer_v1::Request,
+        pending: &Mutex<VirtualAxisEvent>,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>
+    ) {
+        use zwlr_virtual_pointer_v1::Request;
+
+        match request {
+            Request::Motion { time, dx, dy } => state.handle_virtual_input(InputEvent::PointerMotion {
+                event: VirtualMotionEvent { dx, dy, time }
+            }),
+            Request::MotionAbsolute { time, x, y, x_extent, y_extent } => state.handle_virtual_input(InputEvent::PointerMotionAbsolute {
+                event: VirtualMotionAbsoluteEvent { x, y, x_extent, y_extent, time }
+            }),
+            Request::Button { time, button, state: WEnum::Value(button_state) } => {
+                let button_state = match button_state {
+                    wl_pointer::ButtonState::Pressed => ButtonState::Pressed,
+                    _ => ButtonState::Released
+                };
+                state.handle_virtual_input(InputEvent::PointerButton {
+                    event: VirtualButtonEvent { button, state: button_state, time }
+                });
+            }
+            Request::Axis { time, axis: WEnum::Value(axis), value } => {
+                let mut pending = pending.lock().unwrap();
+                pending.time = time;
+                pending.amount[wl_axis_index(axis)] = Some(value);
+            }
+            Request::AxisDiscrete { time, axis: WEnum::Value(axis), value, discrete } => {
+                let mut pending = pending.lock().unwrap();
+                pending.time = time;
+                pending.amount[wl_axis_index(axis)] = Some(value);
+                pending.v120[wl_axis_index(axis)] = Some(discrete as f64 * 120.0);
+            }
+            Request::AxisStop { time, axis: WEnum::Value(axis) } => {
+                // same as a real touchpad, zero finger scrolling stops kinetic scrolling
+                let mut pending = pending.lock().unwrap();
+                pending.time = time;
+                pending.source = Some(AxisSource::Finger);
+                pending.amount[wl_axis_index(axis)] = Some(0.0);
+            }
+            Request::AxisSource { axis_source: WEnum::Value(source) } => {
+                pending.lock().unwrap().source = Some(match source {
+                    wl_pointer::AxisSource::Finger => AxisSource::Finger,
+                    wl_pointer::AxisSource::Continuous => AxisSource::Continuous,
+                    wl_pointer::AxisSource::WheelTilt => AxisSource::WheelTilt,
+                    _ => AxisSource::Wheel
+                });
+            }
+            Request::Frame => {
+                let event = std::mem::take(&mut *pending.lock().unwrap());
+                if event.amount.iter().any(Option::is_some) {
+                    state.handle_virtual_input(InputEvent::PointerAxis { event });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn wl_axis_index(axis: wl_pointer::Axis) -> usize {
+    match axis {
+        wl_pointer::Axis::HorizontalScroll => 0,
+        _ => 1
+    }
+}
@@ -1,6 +1,5 @@
 use smithay::{utils::Serial, wayland::shell::xdg::{PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState}};
-use wayland_protocols::xdg::shell::server::xdg_toplevel;
-use wayland_server::protocol::wl_seat;
+use wayland_server::protocol::{wl_seat, wl_surface::WlSurface};
 
 use crate::compositor::Waycrust;
 
@@ -10,27 +9,43 @@ impl XdgShellHandler for Waycrust {
     }
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
-        surface.with_pending_state(|state| {
-            state.states.set(xdg_toplevel::State::Fullscreen);
-            state.size = self.size;
-        });
-        
-        surface.send_configure();
-
-        self.toplevels.toplevels.push_front(surface.clone());
-
-        self.focus_toplevel(Some(surface));
+        self.toplevels.properties.insert(surface.wl_surface().clone(), Default::default());
+        self.toplevels.pending.push(surface);
     }
 
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        self.toplevels.pending.retain(|s| s != &surface);
         self.toplevels.toplevels.retain(|s| s != &surface);
+        self.toplevels.floating.retain(|s| s != &surface);
+        self.toplevels.scratchpad.retain(|s| s != &surface);
+        self.toplevels.named_scratchpads.retain(|_, s| s != &surface);
+        self.toplevels.properties.remove(surface.wl_surface());
 
         if self.toplevels.focused.as_ref() == Some(&surface) {
-            let next = self.toplevels.toplevels.front().cloned();
+            let next = self.toplevels.floating.last()
+                .or(self.toplevels.toplevels.front())
+                .cloned();
             self.focus_toplevel(next);
         }
     }
 
+    fn app_id_changed(&mut self, surface: ToplevelSurface) {
+        // rules of unmapped windows are applied on their initial commit
+        if self.toplevels.pending.contains(&surface) {
+            return
+        }
+        self.apply_window_rules(&surface);
+        self.claim_scratchpad(&surface);
+    }
+
+    fn title_changed(&mut self, surface: ToplevelSurface) {
+        // rules of unmapped windows are applied on their initial commit
+        if self.toplevels.pending.contains(&surface) {
+            return
+        }
+        self.apply_window_rules(&surface);
+    }
+
     fn new_popup(&mut self, _surface: PopupSurface, _positioner: PositionerState) {
         // Handle popup creation here
     }
@@ -42,4 +57,31 @@ impl XdgShellHandler for Waycrust {
     fn reposition_request(&mut self, _surface: PopupSurface, _positioner: PositionerState, _token: u32) {
         // Handle popup reposition here
     }
-}
\ No newline at end of file
+}
+
+impl Waycrust {
+    /// Maps a toplevel on its initial commit, once the client has set its app id and title, so rules
+    /// decide its first configure and whether it takes focus.
+    pub fn toplevel_committed(&mut self, surface: &WlSurface) {
+        let Some(idx) = self.toplevels.pending.iter().position(|t| t.wl_surface() == surface) else {
+            return
+        };
+        let toplevel = self.toplevels.pending.remove(idx);
+        self.toplevels.toplevels.push_back(toplevel.clone());
+
+        self.apply_window_rules(&toplevel);
+        self.claim_scratchpad(&toplevel);
+
+        let no_focus = self.toplevels.properties.get(surface).is_some_and(|p| p.no_focus);
+        if !no_focus {
+            if let Some(idx) = self.toplevels.toplevels.iter().position(|s| s == &toplevel) {
+                self.toplevels.toplevels.remove(idx);
+                self.toplevels.toplevels.push_front(toplevel.clone());
+            }
+            self.focus_toplevel(Some(toplevel));
+        } else {
+            // the currently visible window stays in front
+            self.configure_toplevel(&toplevel);
+        }
+    }
+}